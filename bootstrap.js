@@ -4,6 +4,7 @@ import init, { Game } from './pkg/cursorarena_io.js';
 async function initMultiplayerGame(mainContent, mapData) {
     const socket = new WebSocket('wss://cursorarena.vovaauer.com:8088');
     let localPlayerId = null;
+    let lastGameState = null;
 
     const canvas = mainContent.querySelector('canvas');
     const ctx = canvas.getContext('2d');
@@ -22,13 +23,22 @@ async function initMultiplayerGame(mainContent, mapData) {
         mouse_dx: 0,
         mouse_dy: 0,
         isMouseDown: false,
+        grabPressed: false,
+        grabReleased: false,
+        spawnRequested: false,
     };
 
     socket.onopen = function(event) {
         console.log('[open] Connection established');
+        const nickname = localStorage.getItem('nickname');
+        if (nickname) {
+            socket.send(JSON.stringify({ type: 'join', name: nickname }));
+        }
         document.addEventListener("mousemove", updatePosition, false);
-        window.addEventListener('mousedown', () => { inputState.isMouseDown = true; });
-        window.addEventListener('mouseup', () => { inputState.isMouseDown = false; });
+        window.addEventListener('mousedown', () => { inputState.isMouseDown = true; inputState.grabPressed = true; });
+        window.addEventListener('mouseup', () => { inputState.isMouseDown = false; inputState.grabReleased = true; });
+        // Only does anything on maps with `player_spawns`.
+        window.addEventListener('keydown', (e) => { if (e.key === 'e') inputState.spawnRequested = true; });
         setInterval(sendInput, 1000 / 60);
     };
 
@@ -38,7 +48,20 @@ async function initMultiplayerGame(mainContent, mapData) {
             if (message.type === 'Welcome') {
                 localPlayerId = message.id;
             } else if (message.type === 'GameState') {
+                lastGameState = message;
                 draw(message);
+            } else if (message.type === 'PlayersUpdate') {
+                // Objects only arrive with full snapshots; reuse the last ones until the next.
+                if (lastGameState) {
+                    lastGameState.players = message.players;
+                    draw(lastGameState);
+                } else {
+                    socket.send(JSON.stringify({ type: 'resync' }));
+                }
+            } else if (message.type === 'RoundEnd') {
+                console.log('[round] Final scores:', message.scores);
+            } else if (message.type === 'Error') {
+                console.warn(`[server] ${message.code}: ${message.message}`);
             }
         } catch (e) {
             console.error('Error parsing message:', e);
@@ -77,13 +100,20 @@ async function initMultiplayerGame(mainContent, mapData) {
             const world_dx = inputState.mouse_dx / scale;
             const world_dy = inputState.mouse_dy / scale;
             const message = {
+                type: 'input',
                 mouse_dx: world_dx,
                 mouse_dy: world_dy,
                 is_mouse_down: inputState.isMouseDown,
+                grab_pressed: inputState.grabPressed,
+                grab_released: inputState.grabReleased,
+                spawn_request: inputState.spawnRequested ? { shape: 'rect' } : null,
             };
             socket.send(JSON.stringify(message));
             inputState.mouse_dx = 0;
             inputState.mouse_dy = 0;
+            inputState.grabPressed = false;
+            inputState.grabReleased = false;
+            inputState.spawnRequested = false;
         }
     }
 
@@ -99,6 +129,15 @@ async function initMultiplayerGame(mainContent, mapData) {
             ctx.fillRect(b.x - b.half_width, b.y - b.half_height, b.half_width * 2, b.half_height * 2);
         });
 
+        ctx.strokeStyle = '#bbb';
+        ctx.lineWidth = 0.03;
+        (gameState.ropes || []).forEach(rope => {
+            ctx.beginPath();
+            ctx.moveTo(rope.x1, rope.y1);
+            ctx.lineTo(rope.x2, rope.y2);
+            ctx.stroke();
+        });
+
         gameState.objects.forEach(obj => {
             ctx.save();
             ctx.translate(obj.x, obj.y);
@@ -152,7 +191,13 @@ async function initLocalGame(mainContent, mapData) {
     const cursorGrabbing = new Image();
     cursorGrabbing.src = 'assets/default_link.png';
 
-    const game = new Game(mapData || null);
+    let game;
+    try {
+        game = new Game(mapData || null);
+    } catch (e) {
+        console.error("Failed to load map:", e);
+        return;
+    }
 
     const WORLD_WIDTH = 16.0;
     const WORLD_HEIGHT = 9.0;
@@ -199,15 +244,31 @@ async function initLocalGame(mainContent, mapData) {
     window.addEventListener('mousedown', () => { inputState.isMouseDown = true; });
     window.addEventListener('mouseup', () => { inputState.isMouseDown = false; });
 
-    function gameLoop() {
-        const world_dx = inputState.mouse_dx / scale;
-        const world_dy = inputState.mouse_dy / scale;
-        inputState.mouse_dx = 0;
-        inputState.mouse_dy = 0;
+    // Physics runs at a fixed 60Hz; frames drawn between ticks blend the last two with `alpha`.
+    const TICK_MS = 1000 / 60;
+    const MAX_CATCH_UP_MS = TICK_MS * 5;
+    let lastFrameTime = null;
+    let accumulator = 0;
+
+    function gameLoop(now) {
+        if (lastFrameTime === null) {
+            lastFrameTime = now;
+            accumulator = TICK_MS;
+        }
+        accumulator = Math.min(accumulator + now - lastFrameTime, MAX_CATCH_UP_MS);
+        lastFrameTime = now;
+
+        while (accumulator >= TICK_MS) {
+            const world_dx = inputState.mouse_dx / scale;
+            const world_dy = inputState.mouse_dy / scale;
+            inputState.mouse_dx = 0;
+            inputState.mouse_dy = 0;
 
-        game.tick(world_dx, world_dy, inputState.isMouseDown);
+            game.tick(world_dx, world_dy, inputState.isMouseDown);
+            accumulator -= TICK_MS;
+        }
 
-        const gameStateString = game.get_game_state();
+        const gameStateString = game.get_interpolated_state(accumulator / TICK_MS);
         const gameState = JSON.parse(gameStateString);
 
         draw(gameState);
@@ -226,6 +287,15 @@ async function initLocalGame(mainContent, mapData) {
             ctx.fillRect(b.x - b.half_width, b.y - b.half_height, b.half_width * 2, b.half_height * 2);
         });
 
+        ctx.strokeStyle = '#bbb';
+        ctx.lineWidth = 0.03;
+        (gameState.ropes || []).forEach(rope => {
+            ctx.beginPath();
+            ctx.moveTo(rope.x1, rope.y1);
+            ctx.lineTo(rope.x2, rope.y2);
+            ctx.stroke();
+        });
+
         gameState.objects.forEach(obj => {
             ctx.save();
             ctx.translate(obj.x, obj.y);
@@ -1,18 +1,114 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::num::NonZero;
 use rapier2d::prelude::*;
-use rapier2d::control::KinematicCharacterController;
+use rapier2d::control::{CharacterAutostep, CharacterLength, KinematicCharacterController};
 use serde::{Serialize, Deserialize};
 use nalgebra::{Point2, Vector2};
+use log::warn;
+use rand::{Rng, SeedableRng};
+use rand::seq::SliceRandom;
+use rand::rngs::StdRng;
 
 pub type PlayerId = u32;
+pub type SensorId = u32;
+// Same value as `PhysicsObject::id`.
+pub type BodyId = u32;
 
 const GRABBABLE_USER_DATA: u128 = 1;
 const DEATH_USER_DATA: u128 = 2;
+// Dynamic and solid, but skipped by the grab query.
+const SOLID_USER_DATA: u128 = 3;
 
 const GROUP_WALLS: u32 = 1 << 0;
 const GROUP_PLAYER: u32 = 1 << 1;
 const GROUP_SQUARE: u32 = 1 << 2;
+const GROUP_PHASE_WALLS: u32 = 1 << 3;
+const GROUP_SENSORS: u32 = 1 << 4;
+
+// Objects collide with each other through layer bits rather than `GROUP_SQUARE`, so maps can name
+// layers that ignore one another. Unlayered objects are on the "default" layer and collide with
+// every layer; named layers take the bits after it.
+const GROUP_DEFAULT_LAYER: u32 = 1 << 5;
+const FIRST_NAMED_LAYER_SHIFT: u32 = 6;
+const MAX_COLLISION_LAYERS: usize = (32 - FIRST_NAMED_LAYER_SHIFT) as usize;
+const ALL_LAYERS: u32 = u32::MAX << 5;
+const DEFAULT_LAYER_NAME: &str = "default";
+const OBJECT_BASE_FILTER: u32 = GROUP_WALLS | GROUP_PHASE_WALLS | GROUP_PLAYER | GROUP_SENSORS;
+
+const PLAYER_RADIUS: f32 = 0.000625;
+const HAZARD_CONTACT_MARGIN: f32 = 0.01;
+// A death by an object the killer grabbed or threw within this many ticks earns them a point.
+const KILL_CREDIT_TICKS: u64 = 180;
+const KILL_POINTS: i32 = 1;
+// Samples of a `PlayerInput::mouse_path` beyond this are ignored.
+const MAX_MOUSE_PATH_SAMPLES: usize = 32;
+// Slower arrivals at a wall, e.g. objects settling against it, aren't reported as impacts.
+const WALL_IMPACT_MIN_SPEED: f32 = 0.5;
+
+const MIN_RESTITUTION: f32 = 0.0;
+const MAX_RESTITUTION: f32 = 1.0;
+const DEFAULT_FRICTION: f32 = 0.5;
+
+const SIDE_LINEAR_DAMPING: f32 = 0.5;
+const SIDE_ANGULAR_DAMPING: f32 = 0.8;
+const TOPDOWN_LINEAR_DAMPING: f32 = 0.05;
+const TOPDOWN_ANGULAR_DAMPING: f32 = 0.1;
+
+const DEFAULT_GRAB_STIFFNESS: f32 = 200.0;
+const DEFAULT_GRAB_DAMPING: f32 = 20.0;
+
+const MIN_OBJECT_SIZE: f32 = 0.05;
+const DEFAULT_DENSITY: f32 = 1.0;
+// Map entities thinner than this are skipped rather than handed to the solver as degenerate shapes.
+const MIN_ENTITY_HALF_EXTENT: f32 = 0.001;
+
+// A held object pressed deeper than this into a wall or static object has its grab swapped for a
+// soft spring until it comes free, so it yields instead of tunneling or exploding.
+const WALL_PENETRATION_SLOP: f32 = 0.02;
+const SOFT_GRAB_STIFFNESS: f32 = 30.0;
+const SOFT_GRAB_DAMPING: f32 = 5.0;
+// Past either of these the simulation is treated as blown up: nothing in a 16x9 arena should move
+// this fast, and healthy contacts overlap by a few hundredths at most.
+const UNSTABLE_SPEED: f32 = 100.0;
+const UNSTABLE_PENETRATION: f32 = 0.25;
+
+const DEFAULT_ROPE_STIFFNESS: f32 = 50.0;
+const DEFAULT_ROPE_DAMPING: f32 = 2.0;
+// Rope slack before the hard limit, as a multiple of its rest length.
+const DEFAULT_ROPE_STRETCH: f32 = 1.5;
+// World units of cursor movement in a tick before a held tool turns to follow it.
+const TOOL_TURN_THRESHOLD: f32 = 0.01;
+// Ticks a `freeze_when_settled` object must sleep before it becomes fixed, and how fast something
+// must be moving when it touches a frozen object to wake it again.
+const FREEZE_AFTER_TICKS: u32 = 120;
+const FREEZE_WAKE_SPEED: f32 = 2.0;
+
+// Entities plus randomly spawned objects; enough for any hand-made map, small enough that a hostile
+// one can't stall the server in `Game::new`.
+pub const DEFAULT_MAX_ENTITIES: usize = 2000;
+
+// In characters, after control characters and surrounding whitespace are stripped.
+pub const MAX_PLAYER_NAME_LEN: usize = 24;
+
+const DEFAULT_THROW_CHARGE_TICKS: u32 = 60;
+const DEFAULT_THROW_CHARGE_DISTANCE: f32 = 4.0;
+const DEFAULT_THROW_CHARGE_BOOST: f32 = 1.0;
+
+// The tethered hand is light and unaffected by gravity, so the spring alone decides how it trails.
+const HAND_MASS: f32 = 0.2;
+const HAND_STIFFNESS: f32 = 60.0;
+const HAND_DAMPING: f32 = 8.0;
+
+const DEFAULT_DASH_DISTANCE: f32 = 1.5;
+const DEFAULT_DASH_COOLDOWN_TICKS: u32 = 90;
+
+// Player spawns in sandbox maps: at most one every half second, and sizes (half extent or radius,
+// world units) are clamped so nobody can fill the arena with a single object.
+const SPAWN_COOLDOWN_TICKS: u32 = 30;
+const DEFAULT_SPAWN_SIZE: f32 = 0.3;
+const MIN_SPAWN_SIZE: f32 = 0.05;
+const MAX_SPAWN_SIZE: f32 = 1.0;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ShapeType {
@@ -20,12 +116,32 @@ pub enum ShapeType {
     Circle,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GrabMode {
+    Rigid,
+    Spring,
+}
+
+// What happens at the arena edges. `Wrap` and `Kill` maps have no walls: things that leave either
+// come back on the opposite side or are sent back to where they spawned.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeBehavior {
+    #[default]
+    Walls,
+    Wrap,
+    Kill,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Boundary {
     pub x: f32,
     pub y: f32,
     pub half_width: f32,
     pub half_height: f32,
+    pub phase: bool,
+    pub lethal: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -35,6 +151,18 @@ pub struct Player {
     pub y: f32,
     pub is_grabbing: bool,
     pub is_over_grabbable: bool,
+    pub charge: f32,
+    // How hard the grab joint pulled during the last physics step; 0 when not grabbing.
+    pub grab_strain: f32,
+    // Ticks until the player can dash again; 0 means ready.
+    pub dash_cooldown: u32,
+    // Ticks left on a stun; a stunned player's input is ignored.
+    pub stunned_ticks: u32,
+    // Freshly spawned players can't be killed by hazards for a while; clients can draw a shield.
+    pub invulnerable: bool,
+    // Where the tethered hand is, when the map uses `hand_tether`.
+    pub hand: Option<[f32; 2]>,
+    pub name: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -48,6 +176,114 @@ pub struct PhysicsObject {
     pub half_width: Option<f32>,
     pub half_height: Option<f32>,
     pub radius: Option<f32>,
+    pub color: Option<String>,
+    pub z_layer: Option<i32>,
+    pub is_grabbable: bool,
+    pub is_grabbed: bool,
+    pub grabbed_by: Option<PlayerId>,
+    // False while the object's group is switched off with `set_group_enabled`.
+    pub enabled: bool,
+    // Tools are held rigidly and point along the holder's movement; `grabbed_by` says who wields one.
+    pub is_tool: bool,
+    // Settled `freeze_when_settled` objects are fixed until something hits them hard or grabs them.
+    pub is_frozen: bool,
+    // 0 for static objects. Not part of `get_state_buffer`.
+    pub mass: f32,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub aabb: Option<[f32; 4]>,
+    // Bodies this one is touching, for spotting stressed stacks. Sent with `aabb`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub contact_count: Option<u8>,
+}
+
+// Everything notable that happened during one `tick`, in the order it happened.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum GameEvent {
+    Grabbed { player_id: PlayerId, object_id: BodyId },
+    Released { player_id: PlayerId, object_id: BodyId, charge: f32 },
+    PlayerDied { player_id: PlayerId },
+    SensorEntered { sensor_id: SensorId, object_id: BodyId },
+    SensorExited { sensor_id: SensorId, object_id: BodyId },
+    Spawned { player_id: PlayerId, object_id: BodyId },
+    // An object started touching arena wall `boundaries[wall_index]`, arriving at `magnitude` world
+    // units per second along the wall's normal.
+    WallImpact { object_id: BodyId, wall_index: usize, magnitude: f32 },
+    // `victim` died to an object `killer` had grabbed or thrown shortly before.
+    Kill { killer: PlayerId, victim: PlayerId },
+    // `from` handed a held object straight to `to`, without it ever being let go.
+    Transferred { from: PlayerId, to: PlayerId, object_id: BodyId },
+}
+
+// Allocation-free snapshots handed to `Game::for_each_object` / `Game::for_each_player`.
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectView {
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub rotation: f32,
+    pub user_data: u128,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerView {
+    pub id: PlayerId,
+    pub x: f32,
+    pub y: f32,
+    pub is_grabbing: bool,
+}
+
+// How long the current grab has been held and how far the cursor has dragged it since.
+#[derive(Debug, Clone, Default)]
+struct GrabCharge {
+    hold_ticks: u32,
+    pull_distance: f32,
+}
+
+impl GrabCharge {
+    fn level(&self, full_ticks: u32, full_distance: f32) -> f32 {
+        let by_time = self.hold_ticks as f32 / full_ticks.max(1) as f32;
+        let by_pull = if full_distance > 0.0 { self.pull_distance / full_distance } else { 0.0 };
+        by_time.max(by_pull).min(1.0)
+    }
+}
+
+// Drops contacts between objects held by two players when either has ghosted the other, or when
+// pvp is off. Player cursors never touch objects themselves, so ghosting acts on what each of them
+// is holding.
+struct GhostHooks<'a> {
+    holders: HashMap<RigidBodyHandle, PlayerId>,
+    // Cursor and hand bodies by player, so held objects pass through whoever holds them.
+    owners: HashMap<RigidBodyHandle, PlayerId>,
+    ghosted: &'a HashSet<(PlayerId, PlayerId)>,
+    pvp: bool,
+}
+
+impl PhysicsHooks for GhostHooks<'_> {
+    fn filter_contact_pair(&self, context: &PairFilterContext) -> Option<SolverFlags> {
+        let holder = |body: Option<RigidBodyHandle>| body.and_then(|handle| self.holders.get(&handle)).copied();
+        let owner = |body: Option<RigidBodyHandle>| body.and_then(|handle| self.owners.get(&handle)).copied();
+        let held_by_owner = |held: Option<RigidBodyHandle>, other: Option<RigidBodyHandle>| holder(held).is_some_and(|player_id| owner(other) == Some(player_id));
+        if held_by_owner(context.rigid_body1, context.rigid_body2) || held_by_owner(context.rigid_body2, context.rigid_body1) {
+            return None;
+        }
+        if let (Some(a), Some(b)) = (holder(context.rigid_body1), holder(context.rigid_body2)) {
+            if (!self.pvp && a != b) || self.ghosted.contains(&ghost_pair(a, b)) {
+                return None;
+            }
+        }
+        Some(SolverFlags::COMPUTE_IMPULSES)
+    }
+}
+
+fn ghost_pair(a: PlayerId, b: PlayerId) -> (PlayerId, PlayerId) {
+    (a.min(b), a.max(b))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RenderData {
+    pub color: Option<String>,
+    pub z_layer: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -55,6 +291,65 @@ pub struct GameState {
     pub players: Vec<Player>,
     pub objects: Vec<PhysicsObject>,
     pub boundaries: Vec<Boundary>,
+    pub gravity: [f32; 2],
+    #[serde(default)]
+    pub ropes: Vec<RopeState>,
+    // See `Game::physics_health`.
+    #[serde(default)]
+    pub unstable: bool,
+}
+
+// A rope's two ends, for clients to draw a line between.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RopeState {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+}
+
+// Measured after every tick, over dynamic bodies and touching contacts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhysicsHealth {
+    pub max_speed: f32,
+    pub max_penetration: f32,
+    // Bodies whose position or velocity has gone NaN or infinite.
+    pub non_finite_bodies: usize,
+}
+
+impl PhysicsHealth {
+    pub fn is_unstable(&self) -> bool {
+        self.non_finite_bodies > 0 || self.max_speed > UNSTABLE_SPEED || self.max_penetration > UNSTABLE_PENETRATION
+    }
+}
+
+// A body blown up by extreme physics can end up at NaN, which JSON can't represent (serde_json
+// writes `null`, which clients then choke on), so clamp anything non-finite to 0.
+fn zero_if_non_finite(value: &mut f32) {
+    if !value.is_finite() {
+        *value = 0.0;
+    }
+}
+
+impl Player {
+    fn zero_non_finite(&mut self) {
+        [&mut self.x, &mut self.y, &mut self.charge, &mut self.grab_strain].into_iter().for_each(zero_if_non_finite);
+    }
+}
+
+impl PhysicsObject {
+    fn zero_non_finite(&mut self) {
+        [&mut self.x, &mut self.y, &mut self.rotation, &mut self.mass].into_iter().for_each(zero_if_non_finite);
+        [&mut self.half_width, &mut self.half_height, &mut self.radius].into_iter().flatten().for_each(zero_if_non_finite);
+        self.aabb.iter_mut().flatten().for_each(zero_if_non_finite);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RayHit {
+    pub object_id: Option<u32>,
+    pub distance: f32,
+    pub normal: [f32; 2],
 }
 
 #[derive(Clone, Deserialize)]
@@ -62,11 +357,45 @@ pub struct PlayerInput {
     pub mouse_dx: f32,
     pub mouse_dy: f32,
     pub is_mouse_down: bool,
+    #[serde(default)]
+    pub scale_factor: Option<f32>,
+    #[serde(default)]
+    pub dash: bool,
+    #[serde(default)]
+    pub dash_dir: (f32, f32),
+    // The button went down / up at some point since the last input, whatever `is_mouse_down` says now.
+    #[serde(default)]
+    pub grab_pressed: bool,
+    #[serde(default)]
+    pub grab_released: bool,
+    // Only honored on maps with `player_spawns` enabled.
+    #[serde(default)]
+    pub spawn_request: Option<SpawnRequest>,
+    // Mouse deltas sampled during the frame, in order, summing to `mouse_dx`/`mouse_dy`. When set,
+    // the cursor moves through them one by one instead of in a single jump.
+    #[serde(default)]
+    pub mouse_path: Option<Vec<(f32, f32)>>,
+}
+
+// An object to drop at the player's cursor: `shape` is "rect" (default) or "circle", and `size` is
+// its half extent or radius in world units.
+#[derive(Clone, Deserialize)]
+pub struct SpawnRequest {
+    pub shape: Option<String>,
+    pub size: Option<f32>,
 }
 
 pub struct Game {
     pub paused: bool,
+    pub freeze_players: bool,
+    // Adds the debug fields `aabb` and `contact_count` to every `PhysicsObject`.
+    pub include_aabbs: bool,
+    pub tick: u64,
     pub gravity: Vector2<f32>,
+    pub gravity_schedule: Option<GravitySchedule>,
+    // Map events in tick order, and the index of the first one that hasn't fired yet.
+    timeline: Vec<TimedEvent>,
+    next_timed_event: usize,
     pub integration_parameters: IntegrationParameters,
     pub physics_pipeline: PhysicsPipeline,
     pub island_manager: IslandManager,
@@ -79,18 +408,273 @@ pub struct Game {
     pub collider_set: ColliderSet,
     pub query_pipeline: QueryPipeline,
     pub character_controller: KinematicCharacterController,
-    pub boundaries: Vec<Boundary>,
+    // What `character_controller` was last configured from, kept for snapshots.
+    player_controller: PlayerControllerData,
+    // `wall_colliders[i]` is the collider for `boundaries[i]`; only `rebuild_boundaries` changes either.
+    boundaries: Vec<Boundary>,
+    edge_behavior: EdgeBehavior,
+    wall_colliders: Vec<ColliderHandle>,
+    bouncy_walls: bool,
+    // The map's named collision layers, so `export_map` can name them again.
+    collision_layers: Vec<String>,
+    // Objects touching each wall at the end of the last tick, so only new contacts count as impacts.
+    wall_contacts: HashSet<(RigidBodyHandle, usize)>,
+    pub camera: CameraHint,
+    pub world_width: f32,
+    pub world_height: f32,
     pub substeps: u32,
+    // Substeps already run by `substep` in the current frame.
+    substeps_done: u32,
     pub players: HashMap<PlayerId, ColliderHandle>,
     pub grab_joints: HashMap<PlayerId, ImpulseJointHandle>,
+    pub grab_mode: GrabMode,
+    pub grab_stiffness: f32,
+    pub grab_damping: f32,
+    // Reach from the player's body to the grab point; `None` is unlimited.
+    pub max_grab_distance: Option<f32>,
+    // New players get a separate hand body springing after their cursor, and grab with the hand.
+    pub hand_tether: bool,
+    hands: HashMap<PlayerId, RigidBodyHandle>,
+    names: HashMap<PlayerId, String>,
+    pub wall_friction: f32,
+    pub player_friction: f32,
+    // Per-second velocity drag on grabbable objects, on top of their own damping; 0 disables it.
+    pub air_resistance: f32,
+    pub throw_charge_ticks: u32,
+    pub throw_charge_distance: f32,
+    pub throw_charge_boost: f32,
+    pub dash_distance: f32,
+    pub dash_cooldown_ticks: u32,
+    dash_cooldowns: HashMap<PlayerId, u32>,
+    stuns: HashMap<PlayerId, u32>,
+    // How long a player is safe from hazards after joining or respawning; 0 disables it.
+    pub spawn_protection_ticks: u32,
+    protections: HashMap<PlayerId, u32>,
+    // Sandbox maps let players drop new objects with `PlayerInput::spawn_request`.
+    pub player_spawns: bool,
+    spawn_cooldowns: HashMap<PlayerId, u32>,
+    // Cap on objects (everything but cursors and hands) that `spawn_object` respects.
+    pub max_objects: usize,
+    // A tighter cap on just the objects that move, counting frozen ones; `None` leaves only `max_objects`.
+    pub max_dynamic_bodies: Option<usize>,
+    object_damping: (f32, f32),
+    object_density: f32,
+    grab_charges: HashMap<PlayerId, GrabCharge>,
+    // Who last grabbed or threw each object, and on which tick, for kill credit.
+    last_touched_by: HashMap<RigidBodyHandle, (PlayerId, u64)>,
+    softened_grabs: HashSet<PlayerId>,
+    // Grabs made and let go within one tick, held through one physics step before the release.
+    pending_releases: HashSet<PlayerId>,
+    ghosted: HashSet<(PlayerId, PlayerId)>,
+    // With pvp off, objects held by different players pass through each other as if every pair of
+    // players had ghosted one another.
+    pub pvp: bool,
+    // Set by `GameBuilder::deterministic`; such a game's snapshots won't restore into a build without the feature.
+    deterministic: bool,
+    scores: HashMap<PlayerId, i32>,
+    sensors: HashMap<ColliderHandle, SensorId>,
+    next_sensor_id: SensorId,
+    sensor_overlaps: Vec<(SensorId, BodyId)>,
+    events: Vec<GameEvent>,
+    health: PhysicsHealth,
+    pub render_data: HashMap<RigidBodyHandle, RenderData>,
+    // Colliders of map entities by their `group`, for `set_group_enabled`.
+    entity_groups: HashMap<String, Vec<ColliderHandle>>,
+    tools: HashSet<RigidBodyHandle>,
+    // Ticks each `freeze_when_settled` object has been asleep, and those that have been frozen.
+    settling: HashMap<RigidBodyHandle, u32>,
+    frozen: HashSet<RigidBodyHandle>,
+    ropes: Vec<Rope>,
+    pub random_spawn: Option<RandomSpawnData>,
+    // Every map object's starting pose, which `restart` puts it back to.
+    spawn_slots: Vec<(RigidBodyHandle, Isometry<f32>)>,
+    pub shuffle_on_restart: bool,
+    restarts: u64,
+    pub spawn_points: Vec<Point2<f32>>,
+    next_spawn_point: usize,
     player_inputs: HashMap<PlayerId, PlayerInput>,
+    // Every body's pose at the start of the last tick, for `get_interpolated_state`.
+    previous_positions: HashMap<RigidBodyHandle, Isometry<f32>>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct MapData {
+    pub mode: Option<String>,
+    pub bouncy_walls: Option<bool>,
+    pub phase_walls: Option<Vec<String>>,
+    pub death_walls: Option<Vec<String>>,
+    pub grab_mode: Option<GrabMode>,
+    pub grab_stiffness: Option<f32>,
+    pub grab_damping: Option<f32>,
     pub gravity: Option<Vector2<f32>>,
+    pub gravity_schedule: Option<GravitySchedule>,
+    pub timeline: Option<Vec<TimedEvent>>,
     pub dimensions: Option<DimensionsData>,
     pub entities: Option<Vec<EntityData>>,
+    pub random_spawn: Option<RandomSpawnData>,
+    pub spawn_points: Option<Vec<[f32; 2]>>,
+    pub solver_iterations: Option<usize>,
+    pub friction_iterations: Option<usize>,
+    pub wall_friction: Option<f32>,
+    pub player_friction: Option<f32>,
+    pub air_resistance: Option<f32>,
+    pub max_grab_distance: Option<f32>,
+    pub collision_layers: Option<Vec<String>>,
+    pub hand_tether: Option<bool>,
+    pub camera: Option<CameraHint>,
+    pub player_controller: Option<PlayerControllerData>,
+    pub ropes: Option<Vec<RopeData>>,
+    pub player_spawns: Option<bool>,
+    pub shuffle_on_restart: Option<bool>,
+    pub edge_behavior: Option<EdgeBehavior>,
+    pub default_density: Option<f32>,
+    pub spawn_protection_ticks: Option<u32>,
+}
+
+// Ties two named entities together center to center. Past `length` the rope pulls like a spring;
+// at `max_length` (default 1.5 times `length`) it goes taut and won't stretch further.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RopeData {
+    pub from: String,
+    pub to: String,
+    pub length: f32,
+    pub max_length: Option<f32>,
+    pub stiffness: Option<f32>,
+    pub damping: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Rope {
+    body1: RigidBodyHandle,
+    body2: RigidBodyHandle,
+    length: f32,
+    max_length: f32,
+    stiffness: f32,
+    damping: f32,
+}
+
+// Where clients should center the view on load, in normalized map coordinates, and how far to zoom.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CameraHint {
+    pub x: f32,
+    pub y: f32,
+    pub zoom: f32,
+}
+
+// Tunes how cursors move against the arena walls. Lengths are in world units and angles in radians;
+// unset fields keep rapier's defaults, and autostep and ground snapping are off unless set.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlayerControllerData {
+    pub slide: Option<bool>,
+    pub autostep_height: Option<f32>,
+    pub autostep_min_width: Option<f32>,
+    pub max_slope_climb_angle: Option<f32>,
+    pub min_slope_slide_angle: Option<f32>,
+    pub snap_to_ground: Option<f32>,
+}
+
+impl Default for CameraHint {
+    fn default() -> Self {
+        Self { x: 0.5, y: 0.5, zoom: 1.0 }
+    }
+}
+
+// Replaces `gravity` over time, driven by the tick counter.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum GravitySchedule {
+    // Each keyframe's gravity holds from its tick until the next one; with a `period` the whole
+    // schedule repeats every `period` ticks.
+    Keyframes { keyframes: Vec<(u64, Vector2<f32>)>, period: Option<u64> },
+    // `base + amplitude * sin(2π * (tick + offset) / period)`.
+    Sine { base: Vector2<f32>, amplitude: Vector2<f32>, period: u64, #[serde(default)] offset: u64 },
+}
+
+impl GravitySchedule {
+    fn gravity_at(&self, tick: u64) -> Option<Vector2<f32>> {
+        match self {
+            GravitySchedule::Keyframes { keyframes, period } => {
+                let tick = period.filter(|period| *period > 0).map_or(tick, |period| tick % period);
+                keyframes.iter()
+                    .filter(|(start, _)| *start <= tick)
+                    .max_by_key(|(start, _)| *start)
+                    .map(|(_, gravity)| *gravity)
+            }
+            GravitySchedule::Sine { base, amplitude, period, offset } => {
+                if *period == 0 {
+                    return None;
+                }
+                let phase = ((tick % period + offset % period) % period) as f32 / *period as f32 * std::f32::consts::TAU;
+                Some(base + amplitude * phase.sin())
+            }
+        }
+    }
+
+    // The same schedule counted from `tick` instead of from 0, for maps exported mid-game. Whatever
+    // gravity holds at `tick` becomes a keyframe at 0.
+    fn rebased(&self, tick: u64) -> Self {
+        match self {
+            GravitySchedule::Keyframes { keyframes, period } => {
+                let period = period.filter(|period| *period > 0);
+                let now = period.map_or(tick, |period| tick % period);
+                let mut rebased: Vec<(u64, Vector2<f32>)> = self.gravity_at(tick).map(|gravity| (0, gravity)).into_iter().collect();
+                rebased.extend(keyframes.iter().filter_map(|(start, gravity)| match period {
+                    Some(period) => (*start < period && *start != now).then(|| ((start + period - now) % period, *gravity)),
+                    None => (*start > now).then(|| (start - now, *gravity)),
+                }));
+                rebased.sort_by_key(|(start, _)| *start);
+                GravitySchedule::Keyframes { keyframes: rebased, period }
+            }
+            GravitySchedule::Sine { base, amplitude, period, offset } => {
+                let offset = if *period == 0 { *offset } else { (offset % period + tick % period) % period };
+                GravitySchedule::Sine { base: *base, amplitude: *amplitude, period: *period, offset }
+            }
+        }
+    }
+}
+
+// Something a map's `timeline` does once the tick counter reaches `tick`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimedEvent {
+    pub tick: u64,
+    #[serde(flatten)]
+    pub action: TimedAction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TimedAction {
+    // `position` is normalized like entity positions; `size` is a half extent or radius.
+    Spawn { shape: String, size: f32, position: [f32; 2] },
+    SetGravity { gravity: Vector2<f32> },
+    SetGroupEnabled { group: String, enabled: bool },
+    // Pins every dynamic object in the group in place, or lets them go again.
+    Freeze { group: String, frozen: bool },
+    Impulse { group: String, impulse: Vector2<f32> },
+}
+
+// Objects scattered at seeded random positions inside `region` (normalized x1, y1, x2, y2).
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RandomSpawnData {
+    pub count: u32,
+    pub seed: u64,
+    pub region: Option<[f32; 4]>,
+    pub shape: Option<String>,
+    pub size: Option<f32>,
+}
+
+impl RandomSpawnData {
+    // The first field that can't be scattered from: a region reaching outside the arena (or not a
+    // number at all), or a size that isn't a positive finite number.
+    fn invalid_field(&self) -> Option<&'static str> {
+        if self.region.is_some_and(|region| region.iter().any(|value| !(0.0..=1.0).contains(value))) {
+            return Some("region");
+        }
+        if self.size.is_some_and(|size| !(size.is_finite() && size > 0.0)) {
+            return Some("size");
+        }
+        None
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -98,6 +682,7 @@ pub struct DimensionsData(pub f32, pub f32);
 
 #[derive(Serialize, Deserialize)]
 pub struct EntityData {
+    pub name: Option<String>,
     pub shape: String,
     pub x1: Option<f32>,
     pub y1: Option<f32>,
@@ -106,349 +691,4633 @@ pub struct EntityData {
     pub x: Option<f32>,
     pub y: Option<f32>,
     pub radius: Option<f32>,
+    pub radius_units: Option<f32>,
     pub is_static: Option<bool>,
     pub is_death: Option<bool>,
+    pub is_grabbable: Option<bool>,
     pub restitution: Option<f32>,
+    pub friction: Option<f32>,
+    pub color: Option<String>,
+    pub z_layer: Option<i32>,
+    pub layer: Option<String>,
+    pub collides_with: Option<Vec<String>>,
+    pub player_collides: Option<bool>,
+    pub group: Option<String>,
+    pub is_tool: Option<bool>,
+    pub freeze_when_settled: Option<bool>,
+    pub density: Option<f32>,
+    pub gravity_scale: Option<f32>,
 }
 
-impl Game {
-    pub fn new(map_data: Option<MapData>) -> Self {
-        let mut rigid_body_set = RigidBodySet::new();
-        let mut collider_set = ColliderSet::new();
-        let mut integration_parameters = IntegrationParameters::default();
-        
-        let substeps = 10;
-        integration_parameters.dt = (1.0 / 60.0) / (substeps as f32);
-
-        integration_parameters.allowed_linear_error = 0.0005;
-        integration_parameters.prediction_distance = 0.001;
-        integration_parameters.min_ccd_dt = integration_parameters.dt;
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameInitError {
+    InvalidDimensions { width: f32, height: f32 },
+    UnknownShape { index: usize, shape: String },
+    MissingField { index: usize, field: &'static str },
+    InvalidSolverIterations { field: &'static str },
+    DuplicateEntityName { name: String },
+    TooManyEntities { count: usize, max: usize },
+    TooManyDynamicBodies { count: usize, max: usize },
+    TooManyCollisionLayers { count: usize, max: usize },
+    UnknownCollisionLayer { index: usize, layer: String },
+    NonFiniteField { index: usize, field: &'static str },
+    UnknownRopeEntity { index: usize, name: String },
+    InvalidRopeLength { index: usize },
+    InvalidRandomSpawn { field: &'static str },
+    InvalidTimedSpawn { index: usize, field: &'static str },
+    DeterminismUnavailable,
+}
 
-        integration_parameters.num_solver_iterations = NonZero::new(8).unwrap();
-        integration_parameters.num_additional_friction_iterations = 4;
+impl fmt::Display for GameInitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameInitError::InvalidDimensions { width, height } => write!(f, "invalid map dimensions {}x{}", width, height),
+            GameInitError::UnknownShape { index, shape } => write!(f, "entity {} has unknown shape \"{}\"", index, shape),
+            GameInitError::MissingField { index, field } => write!(f, "entity {} is missing required field \"{}\"", index, field),
+            GameInitError::InvalidSolverIterations { field } => write!(f, "\"{}\" must be at least 1", field),
+            GameInitError::DuplicateEntityName { name } => write!(f, "entity name \"{}\" is used more than once", name),
+            GameInitError::TooManyCollisionLayers { count, max } => write!(f, "map declares {} collision layers, more than the limit of {}", count, max),
+            GameInitError::UnknownCollisionLayer { index, layer } => write!(f, "entity {} uses undeclared collision layer \"{}\"", index, layer),
+            GameInitError::TooManyEntities { count, max } => write!(f, "map has {} entities, more than the limit of {}", count, max),
+            GameInitError::TooManyDynamicBodies { count, max } => write!(f, "map has {} moving objects, more than the limit of {}", count, max),
+            GameInitError::NonFiniteField { index, field } => write!(f, "entity {} has a non-finite \"{}\"", index, field),
+            GameInitError::UnknownRopeEntity { index, name } => write!(f, "rope {} refers to unknown entity \"{}\"", index, name),
+            GameInitError::InvalidRopeLength { index } => write!(f, "rope {} needs a finite length greater than 0, and a max_length no shorter than it", index),
+            GameInitError::InvalidRandomSpawn { field } => write!(f, "random_spawn has an invalid \"{}\": region values must lie between 0 and 1, and size must be finite and greater than 0", field),
+            GameInitError::InvalidTimedSpawn { index, field } => write!(f, "timeline event {} has an invalid \"{}\": shape must be \"rect\" or \"circle\", size finite and greater than 0, and position finite", index, field),
+            GameInitError::DeterminismUnavailable => write!(f, "deterministic physics needs game_logic built with the \"deterministic\" feature"),
+        }
+    }
+}
 
-        let (world_width, world_height) = if let Some(ref data) = map_data {
-            if let Some(dims) = &data.dimensions {
-                (dims.0, dims.1)
-            } else {
-                (16.0, 9.0)
-            }
-        } else {
-            (16.0, 9.0)
-        };
+impl std::error::Error for GameInitError {}
 
-        let wall_thickness = 0.1;
-        let half_width = world_width / 2.0;
-        let half_height = world_height / 2.0;
-        let mut boundaries = Vec::new();
-        let wall_filter = InteractionGroups::new(GROUP_WALLS.into(), (GROUP_PLAYER | GROUP_SQUARE).into());
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    VersionMismatch { found: u32, expected: u32 },
+    Malformed { reason: String },
+    DeterminismUnavailable,
+}
 
-        let floor_pos = vector![0.0, -half_height];
-        collider_set.insert(ColliderBuilder::cuboid(half_width, wall_thickness).translation(floor_pos).collision_groups(wall_filter).build());
-        boundaries.push(Boundary { x: floor_pos.x, y: floor_pos.y, half_width, half_height: wall_thickness });
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::VersionMismatch { found, expected } => write!(f, "snapshot version {} is not the supported version {}", found, expected),
+            SnapshotError::Malformed { reason } => write!(f, "malformed snapshot: {}", reason),
+            SnapshotError::DeterminismUnavailable => write!(f, "snapshot is of a deterministic game, but game_logic was built without the \"deterministic\" feature"),
+        }
+    }
+}
 
-        let ceil_pos = vector![0.0, half_height];
-        collider_set.insert(ColliderBuilder::cuboid(half_width, wall_thickness).translation(ceil_pos).collision_groups(wall_filter).build());
-        boundaries.push(Boundary { x: ceil_pos.x, y: ceil_pos.y, half_width, half_height: wall_thickness });
+impl std::error::Error for SnapshotError {}
 
-        let left_pos = vector![-half_width, 0.0];
-        collider_set.insert(ColliderBuilder::cuboid(wall_thickness, half_height).translation(left_pos).collision_groups(wall_filter).build());
-        boundaries.push(Boundary { x: left_pos.x, y: left_pos.y, half_width: wall_thickness, half_height });
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerNameError {
+    Empty,
+    TooLong { len: usize, max: usize },
+}
 
-        let right_pos = vector![half_width, 0.0];
-        collider_set.insert(ColliderBuilder::cuboid(wall_thickness, half_height).translation(right_pos).collision_groups(wall_filter).build());
-        boundaries.push(Boundary { x: right_pos.x, y: right_pos.y, half_width: wall_thickness, half_height });
+impl fmt::Display for PlayerNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerNameError::Empty => write!(f, "name is empty"),
+            PlayerNameError::TooLong { len, max } => write!(f, "name is {} characters, more than the limit of {}", len, max),
+        }
+    }
+}
 
-        let mut gravity = vector![0.0, -2.0];
+impl std::error::Error for PlayerNameError {}
 
-        if let Some(ref data) = map_data {
-            if let Some(g) = data.gravity {
-                gravity = g;
-            }
+// Drops control characters (so a name can't break a client's layout) and surrounding whitespace.
+pub fn sanitize_player_name(name: &str) -> Result<String, PlayerNameError> {
+    let cleaned: String = name.chars().filter(|c| !c.is_control()).collect();
+    let cleaned = cleaned.trim();
+    let len = cleaned.chars().count();
+    if len == 0 {
+        return Err(PlayerNameError::Empty);
+    }
+    if len > MAX_PLAYER_NAME_LEN {
+        return Err(PlayerNameError::TooLong { len, max: MAX_PLAYER_NAME_LEN });
+    }
+    Ok(cleaned.to_string())
+}
 
-            if let Some(entities) = &data.entities {
-                let square_filter = InteractionGroups::new(GROUP_SQUARE.into(), (GROUP_WALLS | GROUP_SQUARE | GROUP_PLAYER).into());
-                for entity in entities {
-                    let is_static = entity.is_static.unwrap_or(false);
-                    let is_death = entity.is_death.unwrap_or(false);
-                    let restitution = entity.restitution.unwrap_or(0.0);
+// Bump whenever `GameSnapshot` or any rapier type inside it changes shape, so stale snapshots are
+// rejected up front instead of half-deserializing.
+const SNAPSHOT_VERSION: u32 = 21;
 
-                    let body_builder = if is_static {
-                        RigidBodyBuilder::fixed()
-                    } else {
-                        RigidBodyBuilder::dynamic().ccd_enabled(true).linear_damping(0.5).angular_damping(0.8)
-                    };
-                    
-                    let user_data = if is_death { DEATH_USER_DATA } else { GRABBABLE_USER_DATA };
+#[derive(Deserialize)]
+struct SnapshotHeader {
+    version: u32,
+}
 
-                    let collider_builder = if entity.shape == "rect" {
-                        let x1 = entity.x1.unwrap_or(0.0) * world_width - world_width / 2.0;
-                        let y1 = entity.y1.unwrap_or(0.0) * world_height - world_height / 2.0;
-                        let x2 = entity.x2.unwrap_or(0.0) * world_width - world_width / 2.0;
-                        let y2 = entity.y2.unwrap_or(0.0) * world_height - world_height / 2.0;
-                        let half_width = (x2 - x1) / 2.0;
-                        let half_height = (y2 - y1) / 2.0;
-                        ColliderBuilder::cuboid(half_width.abs(), half_height.abs())
-                            .translation(vector![(x1 + x2) / 2.0, (y1 + y2) / 2.0])
-                    } else if entity.shape == "circle" {
-                        let x = entity.x.unwrap_or(0.0) * world_width - world_width / 2.0;
-                        let y = entity.y.unwrap_or(0.0) * world_height - world_height / 2.0;
-                        let radius = entity.radius.unwrap_or(0.1) * world_width;
-                        ColliderBuilder::ball(radius).translation(vector![x, y])
-                    } else {
-                        continue;
-                    };
+// Everything that outlives a connection. Players, grabs and pending inputs are left out: nobody is
+// connected when a snapshot is restored.
+#[derive(Serialize, Deserialize)]
+struct GameSnapshot {
+    version: u32,
+    tick: u64,
+    gravity: Vector2<f32>,
+    gravity_schedule: Option<GravitySchedule>,
+    timeline: Vec<TimedEvent>,
+    next_timed_event: usize,
+    integration_parameters: IntegrationParameters,
+    island_manager: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    rigid_body_set: RigidBodySet,
+    collider_set: ColliderSet,
+    impulse_joint_set: ImpulseJointSet,
+    multibody_joint_set: MultibodyJointSet,
+    boundaries: Vec<Boundary>,
+    edge_behavior: EdgeBehavior,
+    wall_colliders: Vec<ColliderHandle>,
+    bouncy_walls: bool,
+    collision_layers: Vec<String>,
+    camera: CameraHint,
+    player_controller: PlayerControllerData,
+    world_width: f32,
+    world_height: f32,
+    substeps: u32,
+    grab_mode: GrabMode,
+    grab_stiffness: f32,
+    grab_damping: f32,
+    max_grab_distance: Option<f32>,
+    hand_tether: bool,
+    wall_friction: f32,
+    player_friction: f32,
+    air_resistance: f32,
+    throw_charge_ticks: u32,
+    throw_charge_distance: f32,
+    throw_charge_boost: f32,
+    dash_distance: f32,
+    dash_cooldown_ticks: u32,
+    spawn_protection_ticks: u32,
+    player_spawns: bool,
+    max_objects: usize,
+    max_dynamic_bodies: Option<usize>,
+    pvp: bool,
+    deterministic: bool,
+    object_damping: (f32, f32),
+    object_density: f32,
+    // Handle-keyed maps are stored as pairs since JSON object keys must be strings.
+    render_data: Vec<(RigidBodyHandle, RenderData)>,
+    entity_groups: BTreeMap<String, Vec<ColliderHandle>>,
+    tools: Vec<RigidBodyHandle>,
+    settling: Vec<(RigidBodyHandle, u32)>,
+    frozen: Vec<RigidBodyHandle>,
+    ropes: Vec<Rope>,
+    random_spawn: Option<RandomSpawnData>,
+    spawn_slots: Vec<(RigidBodyHandle, Isometry<f32>)>,
+    shuffle_on_restart: bool,
+    restarts: u64,
+    spawn_points: Vec<Point2<f32>>,
+    sensors: Vec<(ColliderHandle, SensorId)>,
+    next_sensor_id: SensorId,
+}
 
-                    let body = body_builder.user_data(user_data).build();
-                    let collider = collider_builder.restitution(restitution).density(1.0).collision_groups(square_filter).build();
-                    let handle = rigid_body_set.insert(body);
-                    collider_set.insert_with_parent(collider, handle, &mut rigid_body_set);
-                }
+impl MapData {
+    // Layers `other` on top of `self`: its entities are appended and any top-level field it sets
+    // overrides ours. Fails if the combined map names two entities the same.
+    pub fn merge(self, other: MapData) -> Result<MapData, GameInitError> {
+        let entities = match (self.entities, other.entities) {
+            (Some(mut base), Some(layer)) => {
+                base.extend(layer);
+                Some(base)
             }
-        } else {
-            // Default map creation
-            let square_filter = InteractionGroups::new(GROUP_SQUARE.into(), (GROUP_WALLS | GROUP_SQUARE | GROUP_PLAYER).into());
-            for i in 0..8 {
-                for j in 0..5 {
-                    let x = (i as f32 - 3.5) * 1.0;
-                    let y = (j as f32 - 2.0) * 1.0;
-                    let body = RigidBodyBuilder::dynamic().translation(vector![x, y]).user_data(GRABBABLE_USER_DATA).ccd_enabled(true).linear_damping(0.5).angular_damping(0.8).build();
-                    let collider = ColliderBuilder::cuboid(0.3, 0.3).restitution(0.0).density(1.0).collision_groups(square_filter).build();
-                    let handle = rigid_body_set.insert(body);
-                    collider_set.insert_with_parent(collider, handle, &mut rigid_body_set);
-                }
-            }
-        }
+            (base, layer) => layer.or(base),
+        };
 
-        Self {
-            paused: false,
-            gravity,
-            integration_parameters,
-            physics_pipeline: PhysicsPipeline::new(),
-            island_manager: IslandManager::new(),
-            broad_phase: BroadPhase::new(),
-            narrow_phase: NarrowPhase::new(),
-            impulse_joint_set: ImpulseJointSet::new(),
-            multibody_joint_set: MultibodyJointSet::new(),
-            ccd_solver: CCDSolver::new(),
-            rigid_body_set,
-            collider_set,
-            query_pipeline: QueryPipeline::new(),
-            character_controller: KinematicCharacterController::default(),
-            boundaries,
-            substeps,
-            players: HashMap::new(),
-            grab_joints: HashMap::new(),
-            player_inputs: HashMap::new(),
+        let merged = MapData {
+            mode: other.mode.or(self.mode),
+            bouncy_walls: other.bouncy_walls.or(self.bouncy_walls),
+            phase_walls: other.phase_walls.or(self.phase_walls),
+            death_walls: other.death_walls.or(self.death_walls),
+            grab_mode: other.grab_mode.or(self.grab_mode),
+            grab_stiffness: other.grab_stiffness.or(self.grab_stiffness),
+            grab_damping: other.grab_damping.or(self.grab_damping),
+            gravity: other.gravity.or(self.gravity),
+            gravity_schedule: other.gravity_schedule.or(self.gravity_schedule),
+            timeline: other.timeline.or(self.timeline),
+            dimensions: other.dimensions.or(self.dimensions),
+            entities,
+            random_spawn: other.random_spawn.or(self.random_spawn),
+            spawn_points: other.spawn_points.or(self.spawn_points),
+            solver_iterations: other.solver_iterations.or(self.solver_iterations),
+            friction_iterations: other.friction_iterations.or(self.friction_iterations),
+            wall_friction: other.wall_friction.or(self.wall_friction),
+            player_friction: other.player_friction.or(self.player_friction),
+            air_resistance: other.air_resistance.or(self.air_resistance),
+            max_grab_distance: other.max_grab_distance.or(self.max_grab_distance),
+            collision_layers: other.collision_layers.or(self.collision_layers),
+            hand_tether: other.hand_tether.or(self.hand_tether),
+            camera: other.camera.or(self.camera),
+            player_controller: other.player_controller.or(self.player_controller),
+            ropes: other.ropes.or(self.ropes),
+            player_spawns: other.player_spawns.or(self.player_spawns),
+            shuffle_on_restart: other.shuffle_on_restart.or(self.shuffle_on_restart),
+            edge_behavior: other.edge_behavior.or(self.edge_behavior),
+            default_density: other.default_density.or(self.default_density),
+            spawn_protection_ticks: other.spawn_protection_ticks.or(self.spawn_protection_ticks),
+        };
+        merged.check_unique_names()?;
+        Ok(merged)
+    }
+
+    fn check_unique_names(&self) -> Result<(), GameInitError> {
+        let mut seen = HashSet::new();
+        for name in self.entities.iter().flatten().filter_map(|entity| entity.name.as_ref()) {
+            if !seen.insert(name) {
+                return Err(GameInitError::DuplicateEntityName { name: name.clone() });
+            }
         }
+        Ok(())
     }
 
-    pub fn add_player(&mut self, player_id: PlayerId) {
-        let player_filter = InteractionGroups::new(GROUP_PLAYER.into(), GROUP_WALLS.into());
-        let character_body = RigidBodyBuilder::kinematic_position_based().build();
-        let character_handle = self.rigid_body_set.insert(character_body);
-        let character_collider = ColliderBuilder::ball(0.000625).collision_groups(player_filter).build();
-        let character_collider_handle = self.collider_set.insert_with_parent(character_collider, character_handle, &mut self.rigid_body_set);
-        self.players.insert(player_id, character_collider_handle);
+    // Entities that will move: everything but `is_static` ones, plus whatever `random_spawn` scatters.
+    fn dynamic_entity_count(&self) -> usize {
+        self.entities.iter().flatten().filter(|entity| !entity.is_static.unwrap_or(false)).count() + self.random_spawn.as_ref().map_or(0, |spawn| spawn.count as usize)
     }
 
-    pub fn remove_player(&mut self, player_id: PlayerId) {
-        if let Some(collider_handle) = self.players.remove(&player_id) {
-            if let Some(collider) = self.collider_set.get(collider_handle) {
-                if let Some(body_handle) = collider.parent() {
-                    self.rigid_body_set.remove(body_handle, &mut self.island_manager, &mut self.collider_set, &mut self.impulse_joint_set, &mut self.multibody_joint_set, true);
-                }
-            }
+    fn layer_bit(&self, name: &str) -> Option<u32> {
+        if name == DEFAULT_LAYER_NAME {
+            return Some(GROUP_DEFAULT_LAYER);
         }
-        self.grab_joints.remove(&player_id);
-        self.player_inputs.remove(&player_id);
+        self.collision_layers.as_ref()?
+            .iter()
+            .take(MAX_COLLISION_LAYERS)
+            .position(|layer| layer == name)
+            .map(|index| 1 << (FIRST_NAMED_LAYER_SHIFT + index as u32))
     }
 
-    pub fn apply_input(&mut self, player_id: PlayerId, input: PlayerInput) {
-        self.player_inputs.insert(player_id, input);
+    // Membership and filter for an entity's collider. Unknown layer names are ignored here;
+    // `validate` reports them.
+    fn entity_collision_groups(&self, entity: &EntityData) -> InteractionGroups {
+        let layer = entity.layer.as_deref().and_then(|name| self.layer_bit(name)).unwrap_or(GROUP_DEFAULT_LAYER);
+        let mask = entity.collides_with.as_ref().map_or(ALL_LAYERS, |names| {
+            names.iter().filter_map(|name| self.layer_bit(name)).fold(0, |mask, bit| mask | bit)
+        });
+        let player = if entity.player_collides.unwrap_or(true) { 0 } else { GROUP_PLAYER };
+        InteractionGroups::new((GROUP_SQUARE | layer).into(), ((OBJECT_BASE_FILTER & !player) | mask).into())
     }
 
-    pub fn tick(&mut self) {
-        if self.paused {
-            return;
+    pub fn validate(&self) -> Result<(), GameInitError> {
+        self.validate_with_limit(DEFAULT_MAX_ENTITIES)
+    }
+
+    pub fn validate_with_limit(&self, max_entities: usize) -> Result<(), GameInitError> {
+        // Checked first so an oversized map is rejected before anything walks its entity list.
+        let count = self.entities.as_ref().map_or(0, Vec::len) + self.random_spawn.as_ref().map_or(0, |spawn| spawn.count as usize);
+        if count > max_entities {
+            return Err(GameInitError::TooManyEntities { count, max: max_entities });
         }
 
-        // Apply player inputs to move characters
-        for (player_id, character_collider_handle) in &self.players {
-            if let Some(input) = self.player_inputs.get(player_id) {
-                let char_body_handle = self.collider_set[*character_collider_handle].parent().unwrap();
-                let frame_translation = vector![input.mouse_dx, input.mouse_dy];
-                
-                let char_collider = &self.collider_set[*character_collider_handle];
-                let current_position = *self.rigid_body_set[char_body_handle].translation();
-                let filter = QueryFilter::default().groups(InteractionGroups::new(GROUP_PLAYER.into(), GROUP_WALLS.into()));
+        self.check_unique_names()?;
+
+        let layer_count = self.collision_layers.as_ref().map_or(0, Vec::len);
+        if layer_count > MAX_COLLISION_LAYERS {
+            return Err(GameInitError::TooManyCollisionLayers { count: layer_count, max: MAX_COLLISION_LAYERS });
+        }
 
-                let collision = self.character_controller.move_shape(
-                    self.integration_parameters.dt * self.substeps as f32, // Full frame dt
-                    &self.rigid_body_set, 
-                    &self.collider_set, 
-                    &self.query_pipeline,
-                    char_collider.shape(), 
-                    char_collider.position(), 
-                    frame_translation, 
-                    filter, 
-                    |_| {}
-                );
-                if let Some(char_body) = self.rigid_body_set.get_mut(char_body_handle) {
-                    char_body.set_next_kinematic_translation(current_position + collision.translation);
+        if let Some(dims) = &self.dimensions {
+            if !(dims.0.is_finite() && dims.1.is_finite() && dims.0 > 0.0 && dims.1 > 0.0) {
+                return Err(GameInitError::InvalidDimensions { width: dims.0, height: dims.1 });
+            }
+        }
+
+        if self.solver_iterations == Some(0) {
+            return Err(GameInitError::InvalidSolverIterations { field: "solver_iterations" });
+        }
+        if self.friction_iterations == Some(0) {
+            return Err(GameInitError::InvalidSolverIterations { field: "friction_iterations" });
+        }
+
+        if let Some(field) = self.random_spawn.as_ref().and_then(RandomSpawnData::invalid_field) {
+            return Err(GameInitError::InvalidRandomSpawn { field });
+        }
+
+        for (index, event) in self.timeline.iter().flatten().enumerate() {
+            if let TimedAction::Spawn { shape, size, position } = &event.action {
+                let field = if !matches!(shape.as_str(), "rect" | "circle") {
+                    Some("shape")
+                } else if !(size.is_finite() && *size > 0.0) {
+                    Some("size")
+                } else if !position.iter().all(|value| value.is_finite()) {
+                    Some("position")
+                } else {
+                    None
+                };
+                if let Some(field) = field {
+                    return Err(GameInitError::InvalidTimedSpawn { index, field });
                 }
             }
         }
 
-        // Run the physics simulation in substeps
-        for _ in 0..self.substeps {
-            self.physics_pipeline.step(
-                &self.gravity,
-                &self.integration_parameters,
-                &mut self.island_manager,
-                &mut self.broad_phase,
-                &mut self.narrow_phase,
-                &mut self.rigid_body_set,
-                &mut self.collider_set,
-                &mut self.impulse_joint_set,
-                &mut self.multibody_joint_set,
-                &mut self.ccd_solver,
-                None,
-                &(),
-                &(),
-            );
+        for (index, entity) in self.entities.iter().flatten().enumerate() {
+            let required = match entity.shape.as_str() {
+                "rect" => vec![("x1", entity.x1), ("y1", entity.y1), ("x2", entity.x2), ("y2", entity.y2)],
+                "circle" => vec![("x", entity.x), ("y", entity.y)],
+                _ => return Err(GameInitError::UnknownShape { index, shape: entity.shape.clone() }),
+            };
+            if let Some((field, _)) = required.iter().copied().find(|(_, value)| value.is_none()) {
+                return Err(GameInitError::MissingField { index, field });
+            }
+            let mut layers = entity.layer.iter().chain(entity.collides_with.iter().flatten());
+            if let Some(layer) = layers.find(|layer| self.layer_bit(layer).is_none()) {
+                return Err(GameInitError::UnknownCollisionLayer { index, layer: layer.clone() });
+            }
+            let optional = [("radius", entity.radius), ("radius_units", entity.radius_units), ("restitution", entity.restitution), ("friction", entity.friction), ("density", entity.density), ("gravity_scale", entity.gravity_scale)];
+            if let Some((field, _)) = required.into_iter().chain(optional).find(|(_, value)| value.is_some_and(|v| !v.is_finite())) {
+                return Err(GameInitError::NonFiniteField { index, field });
+            }
         }
 
-        // Handle grab logic once per frame, after physics has settled
-        for (player_id, character_collider_handle) in &self.players {
-            if let Some(input) = self.player_inputs.get(player_id) {
-                let char_body_handle = self.collider_set[*character_collider_handle].parent().unwrap();
-                let player_pos = self.rigid_body_set[char_body_handle].translation();
-                let grab_point = Point2::new(player_pos.x, player_pos.y);
-                let grab_filter = QueryFilter::default().groups(InteractionGroups::new(GROUP_PLAYER.into(), GROUP_SQUARE.into()));
-                let mut hovered_object: Option<RigidBodyHandle> = None;
-
-                self.query_pipeline.intersections_with_point(
-                    &self.rigid_body_set, &self.collider_set, &grab_point, grab_filter,
-                    |handle| {
-                        if let Some(collider) = self.collider_set.get(handle) {
-                            if let Some(body) = self.rigid_body_set.get(collider.parent().unwrap()) {
-                                if body.user_data == GRABBABLE_USER_DATA {
-                                    hovered_object = Some(collider.parent().unwrap());
-                                    return false;
-                                }
-                            }
-                        }
-                        true
-                    },
-                );
-
-                if input.is_mouse_down {
-                    if self.grab_joints.get(player_id).is_none() {
-                        if let Some(object_handle) = hovered_object {
-                            let object_body = &self.rigid_body_set[object_handle];
-                            let joint = RevoluteJointBuilder::new().local_anchor1(Point2::origin()).local_anchor2(object_body.position().inverse() * grab_point).build();
-                            let handle = self.impulse_joint_set.insert(char_body_handle, object_handle, joint, true);
-                            self.grab_joints.insert(*player_id, handle);
-                        }
-                    }
-                } else {
-                    if let Some(handle) = self.grab_joints.remove(player_id) {
-                        self.impulse_joint_set.remove(handle, true);
-                    }
+        let names: HashSet<&String> = self.entities.iter().flatten().filter_map(|entity| entity.name.as_ref()).collect();
+        for (index, rope) in self.ropes.iter().flatten().enumerate() {
+            if let Some(name) = [&rope.from, &rope.to].into_iter().find(|name| !names.contains(name)) {
+                return Err(GameInitError::UnknownRopeEntity { index, name: name.clone() });
+            }
+            let max_length = rope.max_length.unwrap_or(rope.length * DEFAULT_ROPE_STRETCH);
+            let tuning = [rope.stiffness, rope.damping].into_iter().flatten().all(|value| value.is_finite() && value >= 0.0);
+            if !(rope.length.is_finite() && rope.length > 0.0 && max_length.is_finite() && max_length >= rope.length && tuning) {
+                return Err(GameInitError::InvalidRopeLength { index });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// StdRng rather than SmallRng: SmallRng picks a different algorithm on 32-bit targets, so the
+// wasm client and the server would disagree on the layout.
+fn random_spawn_positions(spawn: &RandomSpawnData, world_width: f32, world_height: f32) -> Vec<Vector2<f32>> {
+    let mut rng = StdRng::seed_from_u64(spawn.seed);
+    let [x1, y1, x2, y2] = spawn.region.unwrap_or([0.0, 0.0, 1.0, 1.0]);
+    (0..spawn.count)
+        .map(|_| {
+            let x = rng.gen_range(x1.min(x2)..=x1.max(x2));
+            let y = rng.gen_range(y1.min(y2)..=y1.max(y2));
+            vector![x * world_width - world_width / 2.0, y * world_height - world_height / 2.0]
+        })
+        .collect()
+}
+
+// The same point folded back into the arena, as if its opposite edges were joined.
+fn wrap_into_arena(position: Vector2<f32>, width: f32, height: f32) -> Vector2<f32> {
+    vector![(position.x + width / 2.0).rem_euclid(width) - width / 2.0, (position.y + height / 2.0).rem_euclid(height) - height / 2.0]
+}
+
+// Holds a tool by its center at `hand_anchor`, turned `heading` radians from the holder.
+fn tool_joint(hand_anchor: Point2<f32>, heading: f32) -> GenericJoint {
+    FixedJointBuilder::new().local_frame1(Isometry::new(hand_anchor.coords, heading)).local_frame2(Isometry::identity()).build().into()
+}
+
+// Which of the map's named walls a boundary stands for, going by its shape and which side it's on.
+fn wall_name(boundary: &Boundary) -> &'static str {
+    if boundary.half_width >= boundary.half_height {
+        if boundary.y < 0.0 { "floor" } else { "ceiling" }
+    } else if boundary.x < 0.0 {
+        "left"
+    } else {
+        "right"
+    }
+}
+
+// Phase walls stop objects but let cursors through; lethal walls are marked for `touching_hazard`.
+fn wall_collider(boundary: &Boundary, friction: f32, bouncy: bool) -> Collider {
+    let (restitution, combine_rule) = if bouncy {
+        (1.0, CoefficientCombineRule::Max)
+    } else {
+        (0.0, CoefficientCombineRule::Average)
+    };
+    let groups = if boundary.phase {
+        InteractionGroups::new(GROUP_PHASE_WALLS.into(), GROUP_SQUARE.into())
+    } else {
+        InteractionGroups::new(GROUP_WALLS.into(), (GROUP_PLAYER | GROUP_SQUARE).into())
+    };
+    let user_data = if boundary.lethal { DEATH_USER_DATA } else { 0 };
+    ColliderBuilder::cuboid(boundary.half_width, boundary.half_height).translation(vector![boundary.x, boundary.y]).restitution(restitution).restitution_combine_rule(combine_rule).friction(friction).collision_groups(groups).user_data(user_data).build()
+}
+
+fn sanitize_restitution(restitution: f32) -> f32 {
+    if restitution.is_nan() {
+        warn!("Entity restitution is NaN, using {}", MIN_RESTITUTION);
+        return MIN_RESTITUTION;
+    }
+    let clamped = restitution.clamp(MIN_RESTITUTION, MAX_RESTITUTION);
+    if clamped != restitution {
+        warn!("Entity restitution {} is out of range, clamped to {}", restitution, clamped);
+    }
+    clamped
+}
+
+fn sanitize_density(density: f32) -> f32 {
+    if !(density.is_finite() && density > 0.0) {
+        warn!("Density {} is invalid, using {}", density, DEFAULT_DENSITY);
+        return DEFAULT_DENSITY;
+    }
+    density
+}
+
+fn sanitize_friction(friction: f32) -> f32 {
+    if !friction.is_finite() || friction < 0.0 {
+        warn!("Entity friction {} is invalid, clamped to 0", friction);
+        return 0.0;
+    }
+    friction
+}
+
+// The grid of grabbable squares used when no map is given, centered on the arena. `size` is each
+// square's half extent and `spacing` the distance between neighbouring centers, in world units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DefaultMapConfig {
+    pub rows: u32,
+    pub cols: u32,
+    pub spacing: f32,
+    pub size: f32,
+}
+
+impl Default for DefaultMapConfig {
+    fn default() -> Self {
+        Self { rows: 5, cols: 8, spacing: 1.0, size: 0.3 }
+    }
+}
+
+// Every solver setting spelled out rather than taken from `IntegrationParameters::default()`, so a
+// rapier upgrade that retunes its defaults can't quietly change how maps play or break lockstep.
+fn pinned_integration_parameters(substeps: u32) -> IntegrationParameters {
+    let dt = (1.0 / 60.0) / (substeps as f32);
+    IntegrationParameters {
+        dt,
+        min_ccd_dt: dt,
+        erp: 0.6,
+        damping_ratio: 1.0,
+        joint_erp: 1.0,
+        joint_damping_ratio: 1.0,
+        allowed_linear_error: 0.0005,
+        max_penetration_correction: f32::MAX,
+        prediction_distance: 0.001,
+        num_solver_iterations: NonZero::new(8).unwrap(),
+        num_additional_friction_iterations: 4,
+        num_internal_pgs_iterations: 1,
+        min_island_size: 128,
+        max_ccd_substeps: 1,
+    }
+}
+
+// Server-side tuning that doesn't belong in a map file. Anything left unset keeps the value the
+// map (or `Game::new`'s default) gives it.
+#[derive(Default)]
+pub struct GameBuilder {
+    map: Option<MapData>,
+    substeps: Option<u32>,
+    grab_mode: Option<GrabMode>,
+    solver_iterations: Option<usize>,
+    friction_iterations: Option<usize>,
+    max_entities: Option<usize>,
+    max_dynamic_bodies: Option<usize>,
+    pvp: Option<bool>,
+    default_map: Option<DefaultMapConfig>,
+    deterministic: bool,
+}
+
+impl GameBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn map(mut self, map_data: MapData) -> Self {
+        self.map = Some(map_data);
+        self
+    }
+
+    pub fn substeps(mut self, substeps: u32) -> Self {
+        self.substeps = Some(substeps);
+        self
+    }
+
+    pub fn grab_mode(mut self, grab_mode: GrabMode) -> Self {
+        self.grab_mode = Some(grab_mode);
+        self
+    }
+
+    pub fn solver_iterations(mut self, iterations: usize) -> Self {
+        self.solver_iterations = Some(iterations);
+        self
+    }
+
+    pub fn friction_iterations(mut self, iterations: usize) -> Self {
+        self.friction_iterations = Some(iterations);
+        self
+    }
+
+    // Caps the map's entity count, and the objects `spawn_object` will add, instead of `DEFAULT_MAX_ENTITIES`.
+    pub fn max_entities(mut self, max_entities: usize) -> Self {
+        self.max_entities = Some(max_entities);
+        self
+    }
+
+    // Caps the moving objects, from the map and from `spawn_object`, below `max_entities`.
+    pub fn max_dynamic_bodies(mut self, max_dynamic_bodies: usize) -> Self {
+        self.max_dynamic_bodies = Some(max_dynamic_bodies);
+        self
+    }
+
+    // Whether objects held by different players collide; on by default.
+    pub fn pvp(mut self, pvp: bool) -> Self {
+        self.pvp = Some(pvp);
+        self
+    }
+
+    // Shapes the grid built when no `map` is set; ignored otherwise.
+    pub fn default_map(mut self, config: DefaultMapConfig) -> Self {
+        self.default_map = Some(config);
+        self
+    }
+
+    // Insists on the `deterministic` feature, so a lockstep server can't silently run a build
+    // without it, and marks the game so its snapshots won't restore into such a build either. With
+    // the feature, rapier avoids platform-specific float paths and the same binary steps
+    // identically on any machine of the same architecture, given the same map, builder settings
+    // and inputs in the same order; `snapshot` then gives the same bytes too. Games built without
+    // the feature are still repeatable run to run, but not across CPUs. Neither holds across
+    // different builds or rapier versions, and cursor movement and tool headings use std float
+    // math, which may differ between targets.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    pub fn build(self) -> Result<Game, GameInitError> {
+        if self.deterministic && !Game::is_deterministic() {
+            return Err(GameInitError::DeterminismUnavailable);
+        }
+        if let Some(data) = &self.map {
+            data.validate_with_limit(self.max_entities.unwrap_or(DEFAULT_MAX_ENTITIES))?;
+            if let Some(max) = self.max_dynamic_bodies {
+                let count = data.dynamic_entity_count();
+                if count > max {
+                    return Err(GameInitError::TooManyDynamicBodies { count, max });
                 }
             }
         }
+        let mut game = Game::with_default_map(self.map, self.default_map.unwrap_or_default());
+        if let Some(substeps) = self.substeps {
+            game.set_substeps(substeps);
+        }
+        if let Some(grab_mode) = self.grab_mode {
+            game.grab_mode = grab_mode;
+        }
+        if let Some(iterations) = self.solver_iterations {
+            game.set_solver_iterations(iterations);
+        }
+        if let Some(iterations) = self.friction_iterations {
+            game.set_friction_iterations(iterations);
+        }
+        if let Some(max_entities) = self.max_entities {
+            game.max_objects = max_entities;
+        }
+        game.max_dynamic_bodies = self.max_dynamic_bodies;
+        if let Some(pvp) = self.pvp {
+            game.pvp = pvp;
+        }
+        game.deterministic = self.deterministic;
+        Ok(game)
+    }
+}
 
-        self.player_inputs.clear();
-        self.query_pipeline.update(&self.rigid_body_set, &self.collider_set);
+impl Game {
+    pub fn builder() -> GameBuilder {
+        GameBuilder::new()
     }
 
-    pub fn get_game_state(&self) -> GameState {
-        let mut objects = Vec::new();
-        for (handle, body) in self.rigid_body_set.iter() {
-            if self.players.values().any(|&h| h.into_raw_parts() == body.colliders()[0].into_raw_parts()) {
-                continue;
+    // Whether this build has rapier's enhanced determinism; see `GameBuilder::deterministic`.
+    pub const fn is_deterministic() -> bool {
+        cfg!(feature = "deterministic")
+    }
+
+    // Like `new`, but rejects maps that `new` would otherwise silently degrade.
+    pub fn try_new(map_data: Option<MapData>) -> Result<Self, GameInitError> {
+        if let Some(data) = &map_data {
+            data.validate()?;
+        }
+        Ok(Self::new(map_data))
+    }
+
+    pub fn new(map_data: Option<MapData>) -> Self {
+        Self::with_default_map(map_data, DefaultMapConfig::default())
+    }
+
+    fn with_default_map(map_data: Option<MapData>, default_map: DefaultMapConfig) -> Self {
+        let mut rigid_body_set = RigidBodySet::new();
+        let mut collider_set = ColliderSet::new();
+        let substeps = 10;
+        let integration_parameters = pinned_integration_parameters(substeps);
+
+        let (world_width, world_height) = if let Some(ref data) = map_data {
+            if let Some(dims) = &data.dimensions {
+                (dims.0, dims.1)
+            } else {
+                (16.0, 9.0)
             }
+        } else {
+            (16.0, 9.0)
+        };
 
-            for collider_handle in body.colliders() {
-                if let Some(collider) = self.collider_set.get(*collider_handle) {
-                    let (shape, half_width, half_height, radius) = match collider.shape().as_typed_shape() {
-                        TypedShape::Cuboid(c) => (ShapeType::Square, Some(c.half_extents.x), Some(c.half_extents.y), None),
-                        TypedShape::Ball(b) => (ShapeType::Circle, None, None, Some(b.radius)),
-                        _ => continue,
+        // "topdown" is a preset for air-hockey style maps: no gravity, objects glide with little
+        // damping and, unless disabled with `bouncy_walls`, rebound off the arena walls.
+        let is_topdown = map_data.as_ref().and_then(|data| data.mode.as_deref()) == Some("topdown");
+        let (linear_damping, angular_damping) = if is_topdown {
+            (TOPDOWN_LINEAR_DAMPING, TOPDOWN_ANGULAR_DAMPING)
+        } else {
+            (SIDE_LINEAR_DAMPING, SIDE_ANGULAR_DAMPING)
+        };
+        let bouncy_walls = map_data.as_ref().and_then(|data| data.bouncy_walls).unwrap_or(is_topdown);
+
+        let grab_mode = map_data.as_ref().and_then(|data| data.grab_mode).unwrap_or(GrabMode::Rigid);
+        let grab_stiffness = map_data.as_ref().and_then(|data| data.grab_stiffness).unwrap_or(DEFAULT_GRAB_STIFFNESS);
+        let grab_damping = map_data.as_ref().and_then(|data| data.grab_damping).unwrap_or(DEFAULT_GRAB_DAMPING);
+        let max_grab_distance = map_data.as_ref().and_then(|data| data.max_grab_distance).filter(|distance| distance.is_finite() && *distance >= 0.0);
+        let wall_friction = sanitize_friction(map_data.as_ref().and_then(|data| data.wall_friction).unwrap_or(DEFAULT_FRICTION));
+        let player_friction = sanitize_friction(map_data.as_ref().and_then(|data| data.player_friction).unwrap_or(DEFAULT_FRICTION));
+        let air_resistance = map_data.as_ref().and_then(|data| data.air_resistance).filter(|drag| drag.is_finite() && *drag >= 0.0).unwrap_or(0.0);
+        let default_density = sanitize_density(map_data.as_ref().and_then(|data| data.default_density).unwrap_or(DEFAULT_DENSITY));
+
+        let wall_thickness = 0.1;
+        let half_width = world_width / 2.0;
+        let half_height = world_height / 2.0;
+        let mut render_data = HashMap::new();
+        let mut entity_groups: HashMap<String, Vec<ColliderHandle>> = HashMap::new();
+        let mut tools = HashSet::new();
+        let mut settling = HashMap::new();
+        let mut named_bodies: HashMap<&str, RigidBodyHandle> = HashMap::new();
+        let mut ropes = Vec::new();
+
+        // Phase walls ("floor", "ceiling", "left", "right") stop objects but let cursors through.
+        let phase_walls = map_data.as_ref().and_then(|data| data.phase_walls.clone()).unwrap_or_default();
+        let is_phase_wall = |name: &str| phase_walls.iter().any(|wall| wall == name);
+        let death_walls = map_data.as_ref().and_then(|data| data.death_walls.clone()).unwrap_or_default();
+        let is_death_wall = |name: &str| death_walls.iter().any(|wall| wall == name);
+        let wall = |x: f32, y: f32, half_width: f32, half_height: f32, name: &str| Boundary { x, y, half_width, half_height, phase: is_phase_wall(name), lethal: is_death_wall(name) };
+        let edge_behavior = map_data.as_ref().and_then(|data| data.edge_behavior).unwrap_or_default();
+        let boundaries = if edge_behavior == EdgeBehavior::Walls {
+            vec![
+                wall(0.0, -half_height, half_width, wall_thickness, "floor"),
+                wall(0.0, half_height, half_width, wall_thickness, "ceiling"),
+                wall(-half_width, 0.0, wall_thickness, half_height, "left"),
+                wall(half_width, 0.0, wall_thickness, half_height, "right"),
+            ]
+        } else {
+            Vec::new()
+        };
+        let wall_colliders: Vec<ColliderHandle> = boundaries.iter().map(|boundary| collider_set.insert(wall_collider(boundary, wall_friction, bouncy_walls))).collect();
+
+        let spawn_points: Vec<Point2<f32>> = map_data.as_ref()
+            .and_then(|data| data.spawn_points.as_ref())
+            .map(|points| points.iter().map(|[x, y]| Point2::new(x * world_width - half_width, y * world_height - half_height)).collect())
+            .unwrap_or_default();
+
+        // A stable sort keeps events on the same tick in the order the map lists them.
+        let mut timeline = map_data.as_ref().and_then(|data| data.timeline.clone()).unwrap_or_default();
+        timeline.sort_by_key(|event| event.tick);
+
+        let mut gravity = if is_topdown { vector![0.0, 0.0] } else { vector![0.0, -2.0] };
+
+        if let Some(ref data) = map_data {
+            if let Some(g) = data.gravity {
+                gravity = g;
+            }
+
+            if let Some(entities) = &data.entities {
+                for entity in entities {
+                    let square_filter = data.entity_collision_groups(entity);
+                    let is_static = entity.is_static.unwrap_or(false);
+                    let is_death = entity.is_death.unwrap_or(false);
+                    let is_grabbable = entity.is_grabbable.unwrap_or(true);
+                    let restitution = sanitize_restitution(entity.restitution.unwrap_or(0.0));
+                    let friction = sanitize_friction(entity.friction.unwrap_or(DEFAULT_FRICTION));
+
+                    let body_builder = if is_static {
+                        RigidBodyBuilder::fixed()
+                    } else {
+                        let gravity_scale = entity.gravity_scale.filter(|scale| scale.is_finite()).unwrap_or(1.0);
+                        RigidBodyBuilder::dynamic().ccd_enabled(true).linear_damping(linear_damping).angular_damping(angular_damping).gravity_scale(gravity_scale)
+                    };
+                    
+                    let user_data = if is_death {
+                        DEATH_USER_DATA
+                    } else if is_grabbable {
+                        GRABBABLE_USER_DATA
+                    } else {
+                        SOLID_USER_DATA
                     };
 
-                    let position = collider.position();
+                    let collider_builder = if entity.shape == "rect" {
+                        let x1 = entity.x1.unwrap_or(0.0) * world_width - world_width / 2.0;
+                        let y1 = entity.y1.unwrap_or(0.0) * world_height - world_height / 2.0;
+                        let x2 = entity.x2.unwrap_or(0.0) * world_width - world_width / 2.0;
+                        let y2 = entity.y2.unwrap_or(0.0) * world_height - world_height / 2.0;
+                        // No larger than the arena, which still allows full-width floors and walls.
+                        let half_width = ((x2 - x1) / 2.0).abs().min(world_width / 2.0);
+                        let half_height = ((y2 - y1) / 2.0).abs().min(world_height / 2.0);
+                        if half_width < MIN_ENTITY_HALF_EXTENT || half_height < MIN_ENTITY_HALF_EXTENT {
+                            warn!("Skipping rect entity {} with zero area", entity.name.as_deref().unwrap_or("(unnamed)"));
+                            continue;
+                        }
+                        ColliderBuilder::cuboid(half_width, half_height)
+                            .translation(vector![(x1 + x2) / 2.0, (y1 + y2) / 2.0])
+                    } else if entity.shape == "circle" {
+                        let x = entity.x.unwrap_or(0.0) * world_width - world_width / 2.0;
+                        let y = entity.y.unwrap_or(0.0) * world_height - world_height / 2.0;
+                        // `radius` is a fraction of the world width; `radius_units` is absolute and wins when both are set.
+                        let radius = entity.radius_units.unwrap_or_else(|| entity.radius.unwrap_or(0.1) * world_width);
+                        if radius < MIN_ENTITY_HALF_EXTENT {
+                            warn!("Skipping circle entity {} with radius {}", entity.name.as_deref().unwrap_or("(unnamed)"), radius);
+                            continue;
+                        }
+                        let radius = radius.min(world_width.min(world_height) / 2.0);
+                        ColliderBuilder::ball(radius).translation(vector![x, y])
+                    } else {
+                        continue;
+                    };
 
-                    objects.push(PhysicsObject {
-                        id: handle.into_raw_parts().0,
-                        x: position.translation.x, 
-                        y: position.translation.y, 
-                        rotation: position.rotation.angle(),
-                        shape,
-                        user_data: body.user_data,
-                        half_width,
-                        half_height,
-                        radius,
-                    });
+                    let body = body_builder.user_data(user_data).build();
+                    let density = sanitize_density(entity.density.unwrap_or(default_density));
+                    let collider = collider_builder.restitution(restitution).friction(friction).density(density).collision_groups(square_filter).active_hooks(ActiveHooks::FILTER_CONTACT_PAIRS).build();
+                    let handle = rigid_body_set.insert(body);
+                    let collider_handle = collider_set.insert_with_parent(collider, handle, &mut rigid_body_set);
+                    if let Some(group) = &entity.group {
+                        entity_groups.entry(group.clone()).or_default().push(collider_handle);
+                    }
+                    if entity.is_tool.unwrap_or(false) && user_data == GRABBABLE_USER_DATA {
+                        tools.insert(handle);
+                    }
+                    if entity.freeze_when_settled.unwrap_or(false) && !is_static {
+                        settling.insert(handle, 0);
+                    }
+                    if let Some(name) = &entity.name {
+                        named_bodies.insert(name, handle);
+                    }
+
+                    if entity.color.is_some() || entity.z_layer.is_some() {
+                        render_data.insert(handle, RenderData { color: entity.color.clone(), z_layer: entity.z_layer });
+                    }
                 }
             }
-        }
 
-        let mut players = Vec::new();
-        for (player_id, collider_handle) in &self.players {
-            if let Some(collider) = self.collider_set.get(*collider_handle) {
-                if let Some(body) = self.rigid_body_set.get(collider.parent().unwrap()) {
-                    let is_grabbing = self.grab_joints.contains_key(player_id);
-                    let player_pos = body.translation();
-                    let grab_point = Point2::new(player_pos.x, player_pos.y);
-                    let grab_filter = QueryFilter::default().groups(InteractionGroups::new(GROUP_PLAYER.into(), GROUP_SQUARE.into()));
-                    let mut is_over_grabbable = false;
-                    self.query_pipeline.intersections_with_point(
-                        &self.rigid_body_set, &self.collider_set, &grab_point, grab_filter,
-                        |handle| {
-                            if let Some(collider) = self.collider_set.get(handle) {
-                                if let Some(body) = self.rigid_body_set.get(collider.parent().unwrap()) {
-                                    if body.user_data == GRABBABLE_USER_DATA {
-                                        is_over_grabbable = true;
-                                        return false;
-                                    }
-                                }
-                            }
-                            true
-                        },
-                    );
+            // Ropes naming a missing entity are skipped here; `validate` reports them.
+            for rope in data.ropes.iter().flatten() {
+                let (Some(&body1), Some(&body2)) = (named_bodies.get(rope.from.as_str()), named_bodies.get(rope.to.as_str())) else {
+                    continue;
+                };
+                ropes.push(Rope {
+                    body1,
+                    body2,
+                    length: rope.length,
+                    max_length: rope.max_length.unwrap_or(rope.length * DEFAULT_ROPE_STRETCH),
+                    stiffness: rope.stiffness.unwrap_or(DEFAULT_ROPE_STIFFNESS),
+                    damping: rope.damping.unwrap_or(DEFAULT_ROPE_DAMPING),
+                });
+            }
 
-                    players.push(Player {
-                        id: *player_id,
-                        x: body.translation().x,
-                        y: body.translation().y,
-                        is_grabbing,
-                        is_over_grabbable,
-                    });
+            // A random spawn that can't be scattered is skipped here; `validate` reports it.
+            if let Some(spawn) = data.random_spawn.as_ref().filter(|spawn| spawn.invalid_field().is_none()) {
+                let square_filter = InteractionGroups::new((GROUP_SQUARE | GROUP_DEFAULT_LAYER).into(), (OBJECT_BASE_FILTER | ALL_LAYERS).into());
+                let size = spawn.size.unwrap_or(0.3);
+                for position in random_spawn_positions(spawn, world_width, world_height) {
+                    let body = RigidBodyBuilder::dynamic().translation(position).user_data(GRABBABLE_USER_DATA).ccd_enabled(true).linear_damping(linear_damping).angular_damping(angular_damping).build();
+                    let collider_builder = if spawn.shape.as_deref() == Some("circle") {
+                        ColliderBuilder::ball(size)
+                    } else {
+                        ColliderBuilder::cuboid(size, size)
+                    };
+                    let collider = collider_builder.restitution(0.0).density(default_density).collision_groups(square_filter).active_hooks(ActiveHooks::FILTER_CONTACT_PAIRS).build();
+                    let handle = rigid_body_set.insert(body);
+                    collider_set.insert_with_parent(collider, handle, &mut rigid_body_set);
+                }
+            }
+        } else {
+            // Default map creation
+            let square_filter = InteractionGroups::new((GROUP_SQUARE | GROUP_DEFAULT_LAYER).into(), (OBJECT_BASE_FILTER | ALL_LAYERS).into());
+            let DefaultMapConfig { rows, cols, spacing, size } = default_map;
+            for i in 0..cols {
+                for j in 0..rows {
+                    let x = (i as f32 - (cols as f32 - 1.0) / 2.0) * spacing;
+                    let y = (j as f32 - (rows as f32 - 1.0) / 2.0) * spacing;
+                    let body = RigidBodyBuilder::dynamic().translation(vector![x, y]).user_data(GRABBABLE_USER_DATA).ccd_enabled(true).linear_damping(linear_damping).angular_damping(angular_damping).build();
+                    let collider = ColliderBuilder::cuboid(size, size).restitution(0.0).density(1.0).collision_groups(square_filter).active_hooks(ActiveHooks::FILTER_CONTACT_PAIRS).build();
+                    let handle = rigid_body_set.insert(body);
+                    collider_set.insert_with_parent(collider, handle, &mut rigid_body_set);
                 }
             }
         }
 
-        GameState { 
-            players, 
-            objects, 
-            boundaries: self.boundaries.clone(),
+        // No players exist yet, so every dynamic body is a map object.
+        let spawn_slots = rigid_body_set.iter().filter(|(_, body)| body.is_dynamic()).map(|(handle, body)| (handle, *body.position())).collect();
+
+        let mut game = Self {
+            paused: false,
+            freeze_players: true,
+            include_aabbs: false,
+            tick: 0,
+            gravity,
+            gravity_schedule: map_data.as_ref().and_then(|data| data.gravity_schedule.clone()),
+            timeline,
+            next_timed_event: 0,
+            integration_parameters,
+            physics_pipeline: PhysicsPipeline::new(),
+            island_manager: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            impulse_joint_set: ImpulseJointSet::new(),
+            multibody_joint_set: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            rigid_body_set,
+            collider_set,
+            query_pipeline: QueryPipeline::new(),
+            character_controller: KinematicCharacterController::default(),
+            player_controller: PlayerControllerData::default(),
+            boundaries,
+            edge_behavior,
+            wall_colliders,
+            bouncy_walls,
+            collision_layers: map_data.as_ref().and_then(|data| data.collision_layers.clone()).unwrap_or_default(),
+            wall_contacts: HashSet::new(),
+            camera: map_data.as_ref().and_then(|data| data.camera).unwrap_or_default(),
+            world_width,
+            world_height,
+            substeps,
+            substeps_done: 0,
+            players: HashMap::new(),
+            grab_joints: HashMap::new(),
+            grab_mode,
+            grab_stiffness,
+            grab_damping,
+            max_grab_distance,
+            hand_tether: map_data.as_ref().and_then(|data| data.hand_tether).unwrap_or(false),
+            hands: HashMap::new(),
+            names: HashMap::new(),
+            wall_friction,
+            player_friction,
+            air_resistance,
+            throw_charge_ticks: DEFAULT_THROW_CHARGE_TICKS,
+            throw_charge_distance: DEFAULT_THROW_CHARGE_DISTANCE,
+            throw_charge_boost: DEFAULT_THROW_CHARGE_BOOST,
+            dash_distance: DEFAULT_DASH_DISTANCE,
+            dash_cooldown_ticks: DEFAULT_DASH_COOLDOWN_TICKS,
+            dash_cooldowns: HashMap::new(),
+            stuns: HashMap::new(),
+            spawn_protection_ticks: map_data.as_ref().and_then(|data| data.spawn_protection_ticks).unwrap_or(0),
+            protections: HashMap::new(),
+            player_spawns: map_data.as_ref().and_then(|data| data.player_spawns).unwrap_or(false),
+            spawn_cooldowns: HashMap::new(),
+            max_objects: DEFAULT_MAX_ENTITIES,
+            max_dynamic_bodies: None,
+            object_damping: (linear_damping, angular_damping),
+            object_density: default_density,
+            grab_charges: HashMap::new(),
+            last_touched_by: HashMap::new(),
+            softened_grabs: HashSet::new(),
+            pending_releases: HashSet::new(),
+            ghosted: HashSet::new(),
+            pvp: true,
+            deterministic: false,
+            scores: HashMap::new(),
+            sensors: HashMap::new(),
+            next_sensor_id: 0,
+            sensor_overlaps: Vec::new(),
+            events: Vec::new(),
+            health: PhysicsHealth::default(),
+            render_data,
+            entity_groups,
+            tools,
+            settling,
+            frozen: HashSet::new(),
+            ropes,
+            random_spawn: map_data.as_ref().and_then(|data| data.random_spawn.clone()),
+            spawn_slots,
+            shuffle_on_restart: map_data.as_ref().and_then(|data| data.shuffle_on_restart).unwrap_or(false),
+            restarts: 0,
+            spawn_points,
+            next_spawn_point: 0,
+            player_inputs: HashMap::new(),
+            previous_positions: HashMap::new(),
+        };
+
+        if let Some(data) = &map_data {
+            if let Some(iterations) = data.solver_iterations {
+                game.set_solver_iterations(iterations);
+            }
+            if let Some(iterations) = data.friction_iterations {
+                game.set_friction_iterations(iterations);
+            }
+            if let Some(config) = data.player_controller {
+                game.set_player_controller(config);
+            }
         }
+
+        game
     }
 
-    pub fn pause(&mut self) {
-        self.paused = !self.paused;
+    // Keeps each frame at 1/60 s, so more substeps means a smaller physics dt rather than a faster game.
+    pub fn set_substeps(&mut self, substeps: u32) {
+        if substeps == 0 {
+            warn!("Substeps must be at least 1, using 1");
+        }
+        self.substeps = substeps.max(1);
+        self.substeps_done = 0;
+        self.integration_parameters.dt = (1.0 / 60.0) / (self.substeps as f32);
     }
 
-    pub fn restart(&mut self) {
-        for (_, body) in self.rigid_body_set.iter_mut() {
-            if body.is_dynamic() {
-                body.set_position(Isometry::identity(), true);
-                body.set_linvel(vector![0.0, 0.0], true);
-                body.set_angvel(0.0, true);
-            }
+    pub fn set_solver_iterations(&mut self, iterations: usize) {
+        let iterations = NonZero::new(iterations).unwrap_or_else(|| {
+            warn!("Solver iterations must be at least 1, using 1");
+            NonZero::<usize>::MIN
+        });
+        self.integration_parameters.num_solver_iterations = iterations;
+    }
+
+    pub fn set_friction_iterations(&mut self, iterations: usize) {
+        if iterations == 0 {
+            warn!("Friction iterations must be at least 1, using 1");
         }
+        self.integration_parameters.num_additional_friction_iterations = iterations.max(1);
     }
-}
\ No newline at end of file
+
+    // Rebuilds the cursor controller from rapier's defaults, so fields left unset here go back to
+    // them. Negative or non-finite values are ignored.
+    pub fn set_player_controller(&mut self, config: PlayerControllerData) {
+        let valid = |value: &f32| value.is_finite() && *value >= 0.0;
+        let mut controller = KinematicCharacterController::default();
+        if let Some(slide) = config.slide {
+            controller.slide = slide;
+        }
+        controller.autostep = config.autostep_height.filter(valid).map(|height| CharacterAutostep {
+            max_height: CharacterLength::Absolute(height),
+            min_width: CharacterLength::Absolute(config.autostep_min_width.filter(valid).unwrap_or(PLAYER_RADIUS)),
+            include_dynamic_bodies: false,
+        });
+        if let Some(angle) = config.max_slope_climb_angle.filter(valid) {
+            controller.max_slope_climb_angle = angle;
+        }
+        if let Some(angle) = config.min_slope_slide_angle.filter(valid) {
+            controller.min_slope_slide_angle = angle;
+        }
+        controller.snap_to_ground = config.snap_to_ground.filter(valid).map(CharacterLength::Absolute);
+        self.character_controller = controller;
+        self.player_controller = config;
+    }
+
+    pub fn world_dimensions(&self) -> (f32, f32) {
+        (self.world_width, self.world_height)
+    }
+
+    // Switches every object in a map `group` on or off at once, e.g. to open a gate. Disabled objects
+    // don't collide and can't be grabbed, but stay in the game state with `enabled: false`. Returns
+    // false if no entity uses the group.
+    pub fn set_group_enabled(&mut self, group: &str, enabled: bool) -> bool {
+        let Some(colliders) = self.entity_groups.get(group) else {
+            return false;
+        };
+        for handle in colliders {
+            if let Some(collider) = self.collider_set.get_mut(*handle) {
+                collider.set_enabled(enabled);
+            }
+        }
+        // Whatever was resting on a removed gate has to notice it is gone.
+        self.wake_dynamic_bodies();
+        self.query_pipeline.update(&self.rigid_body_set, &self.collider_set);
+        true
+    }
+
+    pub fn boundaries(&self) -> &[Boundary] {
+        &self.boundaries
+    }
+
+    // Replaces every arena wall, e.g. to shrink the ring mid-round. Walls keep the map's friction
+    // and bounciness; each boundary's `phase` and `lethal` flags apply as they do for map walls.
+    pub fn rebuild_boundaries(&mut self, boundaries: Vec<Boundary>) {
+        for handle in self.wall_colliders.drain(..) {
+            self.collider_set.remove(handle, &mut self.island_manager, &mut self.rigid_body_set, true);
+        }
+        self.wall_colliders = boundaries.iter().map(|boundary| self.collider_set.insert(wall_collider(boundary, self.wall_friction, self.bouncy_walls))).collect();
+        self.wall_contacts.clear();
+        self.boundaries = boundaries;
+        self.wake_dynamic_bodies();
+        self.query_pipeline.update(&self.rigid_body_set, &self.collider_set);
+    }
+
+    // Spawns at the map's spawn points in round-robin order, or at the arena center if it has none.
+    // Adding an id that is already playing does nothing and returns false, so a repeated join can't
+    // leave a second cursor behind.
+    pub fn add_player(&mut self, player_id: PlayerId) -> bool {
+        if self.players.contains_key(&player_id) {
+            return false;
+        }
+        let spawn = self.next_spawn_point();
+        self.add_player_at(player_id, spawn)
+    }
+
+    fn next_spawn_point(&mut self) -> Point2<f32> {
+        if self.spawn_points.is_empty() {
+            return Point2::origin();
+        }
+        let point = self.spawn_points[self.next_spawn_point % self.spawn_points.len()];
+        self.next_spawn_point = (self.next_spawn_point + 1) % self.spawn_points.len();
+        point
+    }
+
+    // Sends a player back to a spawn point, dropping anything they were holding.
+    pub fn respawn_player(&mut self, player_id: PlayerId) {
+        let Some(&collider_handle) = self.players.get(&player_id) else {
+            return;
+        };
+        self.force_release(player_id);
+        self.protect(player_id);
+        let spawn = self.next_spawn_point();
+        if let Some(body_handle) = self.collider_set.get(collider_handle).and_then(|collider| collider.parent()) {
+            if let Some(body) = self.rigid_body_set.get_mut(body_handle) {
+                body.set_translation(spawn.coords, true);
+                body.set_next_kinematic_translation(spawn.coords);
+            }
+        }
+    }
+
+    // Handles everything whose center has left the arena on wrap and kill maps. Held objects are let
+    // go first, as are objects held by a wrapping player, since the grab can't reach across the arena.
+    fn apply_edge_behavior(&mut self) {
+        if self.edge_behavior == EdgeBehavior::Walls {
+            return;
+        }
+        let (width, height) = (self.world_width, self.world_height);
+        let player_bodies: HashMap<RigidBodyHandle, PlayerId> = self.players.iter()
+            .filter_map(|(player_id, collider_handle)| Some((self.collider_set.get(*collider_handle)?.parent()?, *player_id)))
+            .collect();
+        let hand_bodies: HashSet<RigidBodyHandle> = self.hands.values().copied().collect();
+        // Map bodies sit at the origin with their shapes offset, so it's the center of mass that has
+        // to leave the arena, not the body's own position.
+        let mut escaped: Vec<RigidBodyHandle> = self.rigid_body_set.iter()
+            .filter(|(handle, body)| !body.is_fixed() && !hand_bodies.contains(handle))
+            .filter(|(_, body)| body.center_of_mass().x.abs() > width / 2.0 || body.center_of_mass().y.abs() > height / 2.0)
+            .map(|(handle, _)| handle)
+            .collect();
+        escaped.sort_by_key(|handle| handle.into_raw_parts());
+
+        let holders = self.grab_holders();
+        for handle in escaped {
+            if let Some(&player_id) = player_bodies.get(&handle) {
+                match self.edge_behavior {
+                    EdgeBehavior::Kill => {
+                        self.respawn_player(player_id);
+                        self.events.push(GameEvent::PlayerDied { player_id });
+                    }
+                    _ => self.wrap_player(player_id, handle),
+                }
+                continue;
+            }
+            if let Some(&player_id) = holders.get(&handle) {
+                self.force_release(player_id);
+            }
+            let slot = self.spawn_slots.iter().find(|(slot, _)| *slot == handle).map(|(_, pose)| *pose);
+            match self.edge_behavior {
+                EdgeBehavior::Kill => match slot {
+                    Some(pose) => {
+                        if let Some(body) = self.rigid_body_set.get_mut(handle) {
+                            body.set_position(pose, true);
+                            body.set_linvel(vector![0.0, 0.0], true);
+                            body.set_angvel(0.0, true);
+                        }
+                    }
+                    // Objects spawned during play have nowhere to go back to.
+                    None => self.remove_object(handle),
+                },
+                _ => {
+                    if let Some(body) = self.rigid_body_set.get_mut(handle) {
+                        let center = body.center_of_mass().coords;
+                        let shift = wrap_into_arena(center, width, height) - center;
+                        body.set_translation(body.translation() + shift, true);
+                    }
+                }
+            }
+        }
+    }
+
+    // Moves a cursor to the opposite edge, bringing its hand along by the same offset.
+    fn wrap_player(&mut self, player_id: PlayerId, body_handle: RigidBodyHandle) {
+        self.force_release(player_id);
+        let Some(body) = self.rigid_body_set.get_mut(body_handle) else {
+            return;
+        };
+        let from = *body.translation();
+        let to = wrap_into_arena(from, self.world_width, self.world_height);
+        body.set_translation(to, true);
+        body.set_next_kinematic_translation(to);
+        if let Some(hand) = self.hands.get(&player_id).and_then(|handle| self.rigid_body_set.get_mut(*handle)) {
+            let hand_position = hand.translation() + (to - from);
+            hand.set_translation(hand_position, true);
+        }
+    }
+
+    // Removes a map object along with its colliders, any grab or rope on it and its entries in the
+    // handle-keyed side maps.
+    fn remove_object(&mut self, handle: RigidBodyHandle) {
+        let holders: Vec<PlayerId> = self.grab_holders().into_iter().filter(|(body, _)| *body == handle).map(|(_, player_id)| player_id).collect();
+        for player_id in holders {
+            self.force_release(player_id);
+        }
+        let colliders: Vec<ColliderHandle> = self.rigid_body_set.get(handle).map(|body| body.colliders().to_vec()).unwrap_or_default();
+        self.rigid_body_set.remove(handle, &mut self.island_manager, &mut self.collider_set, &mut self.impulse_joint_set, &mut self.multibody_joint_set, true);
+        for group in self.entity_groups.values_mut() {
+            group.retain(|collider| !colliders.contains(collider));
+        }
+        self.render_data.remove(&handle);
+        self.tools.remove(&handle);
+        self.settling.remove(&handle);
+        self.frozen.remove(&handle);
+        self.last_touched_by.remove(&handle);
+        self.previous_positions.remove(&handle);
+        self.ropes.retain(|rope| rope.body1 != handle && rope.body2 != handle);
+        self.spawn_slots.retain(|(slot, _)| *slot != handle);
+    }
+
+    // Drops side-map entries for bodies, colliders and joints that no longer exist, e.g. after an
+    // embedder removed bodies straight from the public `rigid_body_set`. Object ids are raw handle
+    // parts, so rapier reuses freed slots and ids are left alone. Returns how many entries went.
+    pub fn compact(&mut self) -> usize {
+        let bodies = &self.rigid_body_set;
+        let colliders = &self.collider_set;
+        let joints = &self.impulse_joint_set;
+        let mut removed = 0;
+        let mut count = |before: usize, after: usize| removed += before - after;
+
+        let before = self.render_data.len();
+        self.render_data.retain(|handle, _| bodies.contains(*handle));
+        count(before, self.render_data.len());
+        let before = self.tools.len();
+        self.tools.retain(|handle| bodies.contains(*handle));
+        count(before, self.tools.len());
+        let before = self.settling.len();
+        self.settling.retain(|handle, _| bodies.contains(*handle));
+        count(before, self.settling.len());
+        let before = self.frozen.len();
+        self.frozen.retain(|handle| bodies.contains(*handle));
+        count(before, self.frozen.len());
+        let before = self.last_touched_by.len();
+        self.last_touched_by.retain(|handle, _| bodies.contains(*handle));
+        count(before, self.last_touched_by.len());
+        let before = self.previous_positions.len();
+        self.previous_positions.retain(|handle, _| bodies.contains(*handle));
+        count(before, self.previous_positions.len());
+        let before = self.spawn_slots.len();
+        self.spawn_slots.retain(|(handle, _)| bodies.contains(*handle));
+        count(before, self.spawn_slots.len());
+        let before = self.ropes.len();
+        self.ropes.retain(|rope| bodies.contains(rope.body1) && bodies.contains(rope.body2));
+        count(before, self.ropes.len());
+        for group in self.entity_groups.values_mut() {
+            let before = group.len();
+            group.retain(|handle| colliders.contains(*handle));
+            count(before, group.len());
+        }
+        let before = self.sensors.len();
+        self.sensors.retain(|handle, _| colliders.contains(*handle));
+        count(before, self.sensors.len());
+        let before = self.grab_joints.len();
+        self.grab_joints.retain(|_, handle| joints.contains(*handle));
+        count(before, self.grab_joints.len());
+
+        let grab_joints = &self.grab_joints;
+        let before = self.grab_charges.len();
+        self.grab_charges.retain(|player_id, _| grab_joints.contains_key(player_id));
+        count(before, self.grab_charges.len());
+        let before = self.softened_grabs.len();
+        self.softened_grabs.retain(|player_id| grab_joints.contains_key(player_id));
+        count(before, self.softened_grabs.len());
+        removed
+    }
+
+    // Whoever last grabbed or threw the object that killed `victim`, if recently enough. Walls and
+    // objects nobody touched earn no credit, and neither does running into your own throw.
+    fn kill_credit(&self, victim: PlayerId, hazard: ColliderHandle) -> Option<PlayerId> {
+        let body = self.collider_set.get(hazard)?.parent()?;
+        let (killer, tick) = *self.last_touched_by.get(&body)?;
+        (killer != victim && self.tick.saturating_sub(tick) <= KILL_CREDIT_TICKS).then_some(killer)
+    }
+
+    // The death object or lethal wall a player at `position` is touching, if any. Phase walls and
+    // objects with `player_collides: false` count too, even though the cursor passes through them.
+    fn touching_hazard(&self, position: &Point2<f32>) -> Option<ColliderHandle> {
+        let probe = Ball::new(PLAYER_RADIUS + HAZARD_CONTACT_MARGIN);
+        let probe_pos = Isometry::translation(position.x, position.y);
+        let filter = QueryFilter::default().groups(InteractionGroups::new(Group::ALL, (GROUP_WALLS | GROUP_PHASE_WALLS | GROUP_SQUARE).into()));
+        let mut hit = None;
+        self.query_pipeline.intersections_with_shape(
+            &self.rigid_body_set, &self.collider_set, &probe_pos, &probe, filter,
+            |handle| {
+                if let Some(collider) = self.collider_set.get(handle) {
+                    let body_is_death = collider.parent()
+                        .and_then(|parent| self.rigid_body_set.get(parent))
+                        .is_some_and(|body| body.user_data == DEATH_USER_DATA);
+                    if collider.user_data == DEATH_USER_DATA || body_is_death {
+                        hit = Some(handle);
+                        return false;
+                    }
+                }
+                true
+            },
+        );
+        hit
+    }
+
+    // Like `add_player`, a no-op returning false if the id is already taken.
+    pub fn add_player_at(&mut self, player_id: PlayerId, pos: Point2<f32>) -> bool {
+        if self.players.contains_key(&player_id) {
+            return false;
+        }
+        let player_filter = InteractionGroups::new(GROUP_PLAYER.into(), GROUP_WALLS.into());
+        let character_body = RigidBodyBuilder::kinematic_position_based().translation(pos.coords).build();
+        let character_handle = self.rigid_body_set.insert(character_body);
+        let character_collider = ColliderBuilder::ball(PLAYER_RADIUS).friction(self.player_friction).collision_groups(player_filter).build();
+        let character_collider_handle = self.collider_set.insert_with_parent(character_collider, character_handle, &mut self.rigid_body_set);
+        self.players.insert(player_id, character_collider_handle);
+
+        if self.hand_tether {
+            // No collider: the hand only carries grabs, it never touches anything itself.
+            let hand_body = RigidBodyBuilder::dynamic().translation(pos.coords).additional_mass(HAND_MASS).gravity_scale(0.0).lock_rotations().build();
+            let hand_handle = self.rigid_body_set.insert(hand_body);
+            let tether = SpringJointBuilder::new(0.0, HAND_STIFFNESS, HAND_DAMPING).build();
+            self.impulse_joint_set.insert(character_handle, hand_handle, tether, true);
+            self.hands.insert(player_id, hand_handle);
+        }
+        self.protect(player_id);
+        true
+    }
+
+    fn protect(&mut self, player_id: PlayerId) {
+        if self.spawn_protection_ticks > 0 {
+            self.protections.insert(player_id, self.spawn_protection_ticks);
+        }
+    }
+
+    pub fn remove_player(&mut self, player_id: PlayerId) {
+        self.force_release(player_id);
+        if let Some(collider_handle) = self.players.remove(&player_id) {
+            if let Some(collider) = self.collider_set.get(collider_handle) {
+                if let Some(body_handle) = collider.parent() {
+                    self.rigid_body_set.remove(body_handle, &mut self.island_manager, &mut self.collider_set, &mut self.impulse_joint_set, &mut self.multibody_joint_set, true);
+                }
+            }
+        }
+        if let Some(hand_handle) = self.hands.remove(&player_id) {
+            self.rigid_body_set.remove(hand_handle, &mut self.island_manager, &mut self.collider_set, &mut self.impulse_joint_set, &mut self.multibody_joint_set, true);
+        }
+        self.dash_cooldowns.remove(&player_id);
+        self.stuns.remove(&player_id);
+        self.protections.remove(&player_id);
+        self.spawn_cooldowns.remove(&player_id);
+        self.player_inputs.remove(&player_id);
+        self.ghosted.retain(|(a, b)| *a != player_id && *b != player_id);
+        self.last_touched_by.retain(|_, (toucher, _)| *toucher != player_id);
+        self.scores.remove(&player_id);
+        self.names.remove(&player_id);
+    }
+
+    // Sanitizes with `sanitize_player_name`; the name shows up in `Player::name` from then on.
+    // Unknown players are ignored.
+    pub fn set_player_name(&mut self, player_id: PlayerId, name: &str) -> Result<(), PlayerNameError> {
+        let name = sanitize_player_name(name)?;
+        if self.players.contains_key(&player_id) {
+            self.names.insert(player_id, name);
+        }
+        Ok(())
+    }
+
+    pub fn player_name(&self, player_id: PlayerId) -> Option<&str> {
+        self.names.get(&player_id).map(String::as_str)
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    // Sorted, so callers iterate players in the same order on every run.
+    pub fn player_ids(&self) -> Vec<PlayerId> {
+        let mut ids: Vec<PlayerId> = self.players.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    // Points are awarded by whatever game rules the embedder runs; the game itself only keeps the tally.
+    pub fn add_score(&mut self, player_id: PlayerId, points: i32) {
+        *self.scores.entry(player_id).or_insert(0) += points;
+    }
+
+    pub fn scores(&self) -> &HashMap<PlayerId, i32> {
+        &self.scores
+    }
+
+    pub fn reset_scores(&mut self) {
+        self.scores.clear();
+    }
+
+    // Lets a player stop colliding with whatever another player is holding (and vice versa), as a
+    // soft mitigation against griefers who block others with grabbed objects.
+    pub fn set_ignore(&mut self, a: PlayerId, b: PlayerId, ignore: bool) {
+        if a == b {
+            return;
+        }
+        if ignore {
+            self.ghosted.insert(ghost_pair(a, b));
+        } else {
+            self.ghosted.remove(&ghost_pair(a, b));
+        }
+    }
+
+    pub fn is_grabbing(&self, player_id: PlayerId) -> bool {
+        self.grab_joints.contains_key(&player_id)
+    }
+
+    fn grab_hovered(&mut self, player_id: PlayerId, char_body_handle: RigidBodyHandle) {
+        let player_pos = *self.rigid_body_set[char_body_handle].translation();
+        let grab_point = Point2::from(player_pos);
+        let Some(object_handle) = self.grabbable_at(&grab_point, &grab_point) else {
+            return;
+        };
+        self.attach_grab(player_id, char_body_handle, object_handle, grab_point);
+        self.events.push(GameEvent::Grabbed { player_id, object_id: object_handle.into_raw_parts().0 });
+    }
+
+    fn attach_grab(&mut self, player_id: PlayerId, char_body_handle: RigidBodyHandle, object_handle: RigidBodyHandle, grab_point: Point2<f32>) {
+        self.unfreeze(object_handle);
+        // With a tethered hand the object hangs off the hand, at the hand's offset from the cursor.
+        let anchor_body_handle = self.hands.get(&player_id).copied().unwrap_or(char_body_handle);
+        let anchor_body = &self.rigid_body_set[anchor_body_handle];
+        let object_body = &self.rigid_body_set[object_handle];
+        // Both anchors are the grab point, in each body's own frame, so the grabbed spot stays under
+        // the cursor and the rest of the object pivots around it however far off center it is.
+        let hand_anchor = anchor_body.position().inverse() * grab_point;
+        // Tools snap their grip (their center) to the hand and keep their current heading.
+        let joint = if self.tools.contains(&object_handle) {
+            tool_joint(hand_anchor, object_body.rotation().angle() - anchor_body.rotation().angle())
+        } else {
+            self.grab_joint(hand_anchor, object_body.position().inverse() * grab_point)
+        };
+        let handle = self.impulse_joint_set.insert(anchor_body_handle, object_handle, joint, true);
+        self.grab_joints.insert(player_id, handle);
+        self.grab_charges.insert(player_id, GrabCharge::default());
+        self.last_touched_by.insert(object_handle, (player_id, self.tick));
+    }
+
+    // Hands an object `from` is holding to `to`, whose cursor must be over it and whose hands must be
+    // free. Both joints are swapped between steps, so the object never falls. Returns false, changing
+    // nothing, if any of that doesn't hold.
+    pub fn transfer_grab(&mut self, from: PlayerId, to: PlayerId, object_id: u32) -> bool {
+        let Some(object_handle) = self.object_handle(object_id) else {
+            return false;
+        };
+        if from == to || self.grab_joints.contains_key(&to) || self.grab_holders().get(&object_handle) != Some(&from) {
+            return false;
+        }
+        let Some(char_body_handle) = self.players.get(&to).and_then(|handle| self.collider_set.get(*handle)).and_then(|collider| collider.parent()) else {
+            return false;
+        };
+        let grab_point = Point2::from(*self.rigid_body_set[char_body_handle].translation());
+        if self.grabbable_at(&grab_point, &grab_point) != Some(object_handle) {
+            return false;
+        }
+        self.force_release(from);
+        self.attach_grab(to, char_body_handle, object_handle, grab_point);
+        self.events.push(GameEvent::Transferred { from, to, object_id });
+        true
+    }
+
+    // A charged release throws the object harder along its current velocity.
+    fn release_grab(&mut self, player_id: PlayerId) {
+        let Some(handle) = self.grab_joints.remove(&player_id) else {
+            return;
+        };
+        self.softened_grabs.remove(&player_id);
+        let charge = self.grab_charges.remove(&player_id)
+            .map_or(0.0, |charge| charge.level(self.throw_charge_ticks, self.throw_charge_distance));
+        if let Some(joint) = self.impulse_joint_set.remove(handle, true) {
+            self.events.push(GameEvent::Released { player_id, object_id: joint.body2.into_raw_parts().0, charge });
+            self.last_touched_by.insert(joint.body2, (player_id, self.tick));
+            if let Some(body) = self.rigid_body_set.get_mut(joint.body2) {
+                let impulse = body.linvel() * body.mass() * charge * self.throw_charge_boost;
+                body.apply_impulse(impulse, true);
+            }
+        }
+    }
+
+    // Ignores the player's input for `ticks` unpaused ticks, unlike `paused` which stops everyone.
+    // A longer stun already running is kept.
+    pub fn stun_player(&mut self, player_id: PlayerId, ticks: u32, drop_held: bool) {
+        if !self.players.contains_key(&player_id) || ticks == 0 {
+            return;
+        }
+        let remaining = self.stuns.entry(player_id).or_insert(0);
+        *remaining = (*remaining).max(ticks);
+        self.player_inputs.remove(&player_id);
+        if drop_held {
+            self.force_release(player_id);
+        }
+    }
+
+    // Drops whatever the player is holding without waiting for a mouse-up, e.g. for stuns.
+    pub fn force_release(&mut self, player_id: PlayerId) {
+        if let Some(handle) = self.grab_joints.remove(&player_id) {
+            self.impulse_joint_set.remove(handle, true);
+        }
+        self.grab_charges.remove(&player_id);
+        self.softened_grabs.remove(&player_id);
+        self.pending_releases.remove(&player_id);
+    }
+
+    // Which player each cursor and hand body belongs to.
+    fn body_owners(&self) -> HashMap<RigidBodyHandle, PlayerId> {
+        self.players.iter()
+            .filter_map(|(player_id, collider_handle)| Some((self.collider_set.get(*collider_handle)?.parent()?, *player_id)))
+            .chain(self.hands.iter().map(|(player_id, handle)| (*handle, *player_id)))
+            .collect()
+    }
+
+    // Which player holds each grabbed body.
+    fn grab_holders(&self) -> HashMap<RigidBodyHandle, PlayerId> {
+        self.grab_joints.iter()
+            .filter_map(|(player_id, joint_handle)| Some((self.impulse_joint_set.get(*joint_handle)?.body2, *player_id)))
+            .collect()
+    }
+
+    pub fn release_all_grabs(&mut self) {
+        let holders: Vec<PlayerId> = self.grab_joints.keys().copied().collect();
+        for player_id in holders {
+            self.force_release(player_id);
+        }
+    }
+
+    // 0 when not grabbing, rising to 1 as the grab is held longer or dragged further.
+    pub fn grab_charge(&self, player_id: PlayerId) -> f32 {
+        self.grab_charges.get(&player_id)
+            .map_or(0.0, |charge| charge.level(self.throw_charge_ticks, self.throw_charge_distance))
+    }
+
+    // Magnitude of the linear impulse the solver applied through the player's grab joint in the last
+    // step, so it only changes when the world is stepped.
+    pub fn grab_strain(&self, player_id: PlayerId) -> f32 {
+        self.grab_joints.get(&player_id)
+            .and_then(|handle| self.impulse_joint_set.get(*handle))
+            .map_or(0.0, |joint| vector![joint.impulses.x, joint.impulses.y].norm())
+    }
+
+    // True once every dynamic body has fallen asleep and nobody is holding anything, so stepping
+    // the world would change nothing until the next input arrives.
+    pub fn is_settled(&self) -> bool {
+        self.grab_joints.is_empty() && self.rigid_body_set.iter().all(|(_, body)| !body.is_dynamic() || body.is_sleeping())
+    }
+
+    fn object_handle(&self, id: u32) -> Option<RigidBodyHandle> {
+        self.rigid_body_set.iter()
+            .map(|(handle, _)| handle)
+            .find(|handle| handle.into_raw_parts().0 == id)
+    }
+
+    // Grows or shrinks an object about its center, keeping its id, velocity and any grabs on it.
+    // The factor is limited so the object stays between `MIN_OBJECT_SIZE` and a quarter of the
+    // arena's smaller side. Returns false if there is no such object.
+    pub fn scale_object(&mut self, id: u32, factor: f32) -> bool {
+        match self.object_handle(id) {
+            Some(handle) => self.scale_body(handle, factor),
+            None => false,
+        }
+    }
+
+    fn scale_body(&mut self, body_handle: RigidBodyHandle, factor: f32) -> bool {
+        if !(factor.is_finite() && factor > 0.0) {
+            return false;
+        }
+        let Some(&collider_handle) = self.rigid_body_set.get(body_handle).and_then(|body| body.colliders().first()) else {
+            return false;
+        };
+        let max_size = self.world_width.min(self.world_height) / 4.0;
+        let clamp_factor = |smallest: f32, largest: f32| factor.max(MIN_OBJECT_SIZE / smallest).min(max_size / largest);
+
+        let Some(collider) = self.collider_set.get_mut(collider_handle) else {
+            return false;
+        };
+        let (shape, applied) = match collider.shape().as_typed_shape() {
+            TypedShape::Cuboid(c) => {
+                let applied = clamp_factor(c.half_extents.min(), c.half_extents.max());
+                (SharedShape::cuboid(c.half_extents.x * applied, c.half_extents.y * applied), applied)
+            }
+            TypedShape::Ball(b) => {
+                let applied = clamp_factor(b.radius, b.radius);
+                (SharedShape::ball(b.radius * applied), applied)
+            }
+            _ => return false,
+        };
+        collider.set_shape(shape);
+
+        // Grab anchors live in the body frame, so scale them about the collider's center too.
+        let center = collider.position_wrt_parent().map_or(Point2::origin(), |pos| Point2::from(pos.translation.vector));
+        for joint_handle in self.grab_joints.values() {
+            if let Some(joint) = self.impulse_joint_set.get_mut(*joint_handle) {
+                if joint.body2 == body_handle {
+                    let anchor = joint.data.local_anchor2();
+                    joint.data.set_local_anchor2(center + (anchor - center) * applied);
+                }
+            }
+        }
+        true
+    }
+
+    fn apply_gravity_schedule(&mut self) {
+        let Some(gravity) = self.gravity_schedule.as_ref().and_then(|schedule| schedule.gravity_at(self.tick)) else {
+            return;
+        };
+        if gravity != self.gravity {
+            self.gravity = gravity;
+            self.wake_dynamic_bodies();
+        }
+    }
+
+    // Fires every timeline event due by this tick. Ticks spent paused don't count.
+    fn run_timeline(&mut self) {
+        while let Some(event) = self.timeline.get(self.next_timed_event).filter(|event| event.tick <= self.tick) {
+            let action = event.action.clone();
+            self.next_timed_event += 1;
+            match action {
+                TimedAction::Spawn { shape, size, position: [x, y] } => {
+                    let position = Point2::new((x - 0.5) * self.world_width, (y - 0.5) * self.world_height);
+                    if self.spawn_object(&shape, size, position).is_none() {
+                        warn!("Timeline spawn at tick {} failed", self.tick);
+                    }
+                }
+                TimedAction::SetGravity { gravity } => self.set_gravity(gravity.x, gravity.y),
+                TimedAction::SetGroupEnabled { group, enabled } => {
+                    self.set_group_enabled(&group, enabled);
+                }
+                TimedAction::Freeze { group, frozen } => self.set_group_frozen(&group, frozen),
+                TimedAction::Impulse { group, impulse } => {
+                    for handle in self.group_bodies(&group) {
+                        if let Some(body) = self.rigid_body_set.get_mut(handle) {
+                            body.apply_impulse(impulse, true);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn group_bodies(&self, group: &str) -> Vec<RigidBodyHandle> {
+        let mut bodies: Vec<RigidBodyHandle> = self.entity_groups.get(group).into_iter().flatten()
+            .filter_map(|handle| self.collider_set.get(*handle)?.parent())
+            .collect();
+        bodies.sort_by_key(|handle| handle.into_raw_parts());
+        bodies.dedup();
+        bodies
+    }
+
+    // Freezes a group's dynamic objects the way `freeze_when_settled` does, so a hard hit or a grab
+    // still wakes them.
+    pub fn set_group_frozen(&mut self, group: &str, frozen: bool) {
+        for handle in self.group_bodies(group) {
+            if !frozen {
+                self.unfreeze(handle);
+                continue;
+            }
+            if let Some(body) = self.rigid_body_set.get_mut(handle) {
+                if body.is_dynamic() {
+                    body.set_body_type(RigidBodyType::Fixed, false);
+                    self.frozen.insert(handle);
+                }
+            }
+        }
+    }
+
+    // Overrides gravity live, e.g. from a sandbox admin. This also cancels any map gravity schedule,
+    // which would otherwise overwrite the new value on the next tick.
+    pub fn set_gravity(&mut self, x: f32, y: f32) {
+        self.gravity_schedule = None;
+        self.gravity = vector![x, y];
+        self.wake_dynamic_bodies();
+    }
+
+    fn handle_spawn_requests(&mut self) {
+        if !self.player_spawns {
+            return;
+        }
+        let mut requests: Vec<(PlayerId, SpawnRequest)> = self.player_inputs.iter()
+            .filter(|(player_id, _)| !self.spawn_cooldowns.contains_key(player_id))
+            .filter_map(|(player_id, input)| Some((*player_id, input.spawn_request.clone()?)))
+            .collect();
+        requests.sort_unstable_by_key(|(player_id, _)| *player_id);
+        for (player_id, request) in requests {
+            let Some(position) = self.player_position(player_id) else {
+                continue;
+            };
+            let shape = request.shape.as_deref().unwrap_or("rect");
+            let size = request.size.filter(|size| size.is_finite()).unwrap_or(DEFAULT_SPAWN_SIZE).clamp(MIN_SPAWN_SIZE, MAX_SPAWN_SIZE);
+            if let Some(handle) = self.spawn_object(shape, size, position) {
+                self.spawn_cooldowns.insert(player_id, SPAWN_COOLDOWN_TICKS);
+                self.events.push(GameEvent::Spawned { player_id, object_id: handle.into_raw_parts().0 });
+            }
+        }
+    }
+
+    fn player_position(&self, player_id: PlayerId) -> Option<Point2<f32>> {
+        let collider = self.collider_set.get(*self.players.get(&player_id)?)?;
+        let body = self.rigid_body_set.get(collider.parent()?)?;
+        Some(Point2::from(*body.translation()))
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.rigid_body_set.len() - self.players.len() - self.hands.len()
+    }
+
+    // Objects that move, including frozen ones since they will again. Hands are left out.
+    pub fn dynamic_body_count(&self) -> usize {
+        let hands: HashSet<RigidBodyHandle> = self.hands.values().copied().collect();
+        self.rigid_body_set.iter().filter(|(handle, body)| (body.is_dynamic() || self.frozen.contains(handle)) && !hands.contains(handle)).count()
+    }
+
+    // Adds a grabbable object at runtime, with the same physics as map objects. `shape` is "rect"
+    // or "circle" and `size` its half extent or radius. Returns `None` for an unknown shape, a size
+    // that isn't finite and positive, or once the game holds `max_objects` objects, or
+    // `max_dynamic_bodies` moving ones.
+    pub fn spawn_object(&mut self, shape: &str, size: f32, position: Point2<f32>) -> Option<RigidBodyHandle> {
+        if !size.is_finite() || size <= 0.0 {
+            return None;
+        }
+        if self.object_count() >= self.max_objects || self.max_dynamic_bodies.is_some_and(|max| self.dynamic_body_count() >= max) {
+            return None;
+        }
+        let collider_builder = match shape {
+            "rect" => ColliderBuilder::cuboid(size, size),
+            "circle" => ColliderBuilder::ball(size),
+            _ => return None,
+        };
+        let (linear_damping, angular_damping) = self.object_damping;
+        let square_filter = InteractionGroups::new((GROUP_SQUARE | GROUP_DEFAULT_LAYER).into(), (OBJECT_BASE_FILTER | ALL_LAYERS).into());
+        let body = RigidBodyBuilder::dynamic().translation(position.coords).user_data(GRABBABLE_USER_DATA).ccd_enabled(true).linear_damping(linear_damping).angular_damping(angular_damping).build();
+        let collider = collider_builder.restitution(0.0).density(self.object_density).collision_groups(square_filter).active_hooks(ActiveHooks::FILTER_CONTACT_PAIRS).build();
+        let handle = self.rigid_body_set.insert(body);
+        self.collider_set.insert_with_parent(collider, handle, &mut self.rigid_body_set);
+        Some(handle)
+    }
+
+    // Slows each moving grabbable object by an impulse proportional to its velocity. The drag is
+    // capped at the object's full velocity so a huge `air_resistance` stops it rather than reversing it.
+    fn apply_air_resistance(&mut self) {
+        if self.air_resistance <= 0.0 {
+            return;
+        }
+        let tick_seconds = self.integration_parameters.dt * self.substeps as f32;
+        let drag = (self.air_resistance * tick_seconds).min(1.0);
+        for (_, body) in self.rigid_body_set.iter_mut() {
+            if !body.is_dynamic() || body.is_sleeping() || body.user_data != GRABBABLE_USER_DATA {
+                continue;
+            }
+            let impulse = -body.linvel() * body.mass() * drag;
+            body.apply_impulse(impulse, true);
+        }
+    }
+
+    // Ropes act once per tick with impulses: a damped spring beyond the rest length, then a hard stop
+    // at `max_length` that cancels any further separating speed and pulls the ends back in, split by
+    // inverse mass so a fixed end never moves.
+    fn apply_ropes(&mut self) {
+        let tick_seconds = self.integration_parameters.dt * self.substeps as f32;
+        for rope in &self.ropes {
+            let (Some(body1), Some(body2)) = (self.rigid_body_set.get(rope.body1), self.rigid_body_set.get(rope.body2)) else {
+                continue;
+            };
+            let inv_mass = |body: &RigidBody| if body.is_dynamic() && body.mass() > 0.0 { 1.0 / body.mass() } else { 0.0 };
+            let (inv_mass1, inv_mass2) = (inv_mass(body1), inv_mass(body2));
+            let inv_mass_sum = inv_mass1 + inv_mass2;
+            let offset = self.rope_end(body2, body2.position()) - self.rope_end(body1, body1.position());
+            let distance = offset.norm();
+            if inv_mass_sum <= 0.0 || distance <= rope.length {
+                continue;
+            }
+            let Some(dir) = offset.try_normalize(f32::EPSILON) else {
+                continue;
+            };
+
+            let separating_speed = (body2.linvel() - body1.linvel()).dot(&dir);
+            let mut impulse = (rope.stiffness * (distance - rope.length) + rope.damping * separating_speed) * tick_seconds;
+            let mut correction = 0.0;
+            if distance >= rope.max_length {
+                impulse = impulse.max(separating_speed.max(0.0) / inv_mass_sum);
+                correction = distance - rope.max_length;
+            }
+
+            let (handle1, handle2) = (rope.body1, rope.body2);
+            for (handle, inv_mass, sign) in [(handle1, inv_mass1, 1.0), (handle2, inv_mass2, -1.0)] {
+                if inv_mass <= 0.0 {
+                    continue;
+                }
+                if let Some(body) = self.rigid_body_set.get_mut(handle) {
+                    body.apply_impulse(dir * impulse * sign, true);
+                    if correction > 0.0 {
+                        let translation = body.translation() + dir * correction * sign * inv_mass / inv_mass_sum;
+                        body.set_translation(translation, true);
+                    }
+                }
+            }
+        }
+    }
+
+    // Where a rope attaches to a body at `position`: the center of its first collider, since map
+    // bodies sit at the origin with their shapes offset from it.
+    fn rope_end(&self, body: &RigidBody, position: &Isometry<f32>) -> Vector2<f32> {
+        let local = body.colliders().first().and_then(|handle| self.collider_set.get(*handle)).and_then(|collider| collider.position_wrt_parent());
+        local.map_or(position.translation.vector, |local| (position * local).translation.vector)
+    }
+
+    // Sleeping bodies would otherwise hang in place after gravity changes.
+    fn wake_dynamic_bodies(&mut self) {
+        for (_, body) in self.rigid_body_set.iter_mut() {
+            if body.is_dynamic() {
+                body.wake_up(true);
+            }
+        }
+    }
+
+    // A later input in the same tick replaces an earlier one, except that press and release edges
+    // carry over so they aren't lost.
+    pub fn apply_input(&mut self, player_id: PlayerId, mut input: PlayerInput) {
+        if let Some(previous) = self.player_inputs.get(&player_id) {
+            input.grab_pressed |= previous.grab_pressed;
+            input.grab_released |= previous.grab_released;
+            if input.spawn_request.is_none() {
+                input.spawn_request = previous.spawn_request.clone();
+            }
+        }
+        self.player_inputs.insert(player_id, input);
+    }
+
+    // Apply player inputs to move characters
+    fn move_players(&mut self) {
+        for (player_id, character_collider_handle) in &self.players {
+            if let Some(input) = self.player_inputs.get(player_id) {
+                let char_body_handle = self.collider_set[*character_collider_handle].parent().unwrap();
+                // Each sample of a mouse path is its own controller move, so a fast flick follows its
+                // path along the walls instead of cutting straight to where it ended.
+                let mut moves: Vec<Vector2<f32>> = match &input.mouse_path {
+                    Some(path) if !path.is_empty() => path.iter().take(MAX_MOUSE_PATH_SAMPLES).map(|(dx, dy)| vector![*dx, *dy]).collect(),
+                    _ => vec![vector![input.mouse_dx, input.mouse_dy]],
+                };
+
+                // A dash is added on top of the mouse delta, so the controller still stops it at walls.
+                if input.dash && !self.dash_cooldowns.contains_key(player_id) {
+                    if let Some(dir) = vector![input.dash_dir.0, input.dash_dir.1].try_normalize(f32::EPSILON) {
+                        if let Some(last) = moves.last_mut() {
+                            *last += dir * self.dash_distance;
+                        }
+                        self.dash_cooldowns.insert(*player_id, self.dash_cooldown_ticks);
+                    }
+                }
+
+                let char_collider = &self.collider_set[*character_collider_handle];
+                let current_position = *self.rigid_body_set[char_body_handle].translation();
+                let filter = QueryFilter::default().groups(InteractionGroups::new(GROUP_PLAYER.into(), GROUP_WALLS.into()));
+                let move_dt = self.integration_parameters.dt * self.substeps as f32 / moves.len() as f32; // Full frame dt, shared by the moves
+
+                let mut shape_position = *char_collider.position();
+                let mut translation = vector![0.0, 0.0];
+                for movement in moves {
+                    let collision = self.character_controller.move_shape(
+                        move_dt,
+                        &self.rigid_body_set, 
+                        &self.collider_set, 
+                        &self.query_pipeline,
+                        char_collider.shape(), 
+                        &shape_position, 
+                        movement, 
+                        filter, 
+                        |_| {}
+                    );
+                    shape_position.translation.vector += collision.translation;
+                    translation += collision.translation;
+                }
+                let next_position = current_position + translation;
+                if self.paused {
+                    // No physics step will apply a kinematic target, so place the body and its collider directly.
+                    if let Some(char_body) = self.rigid_body_set.get_mut(char_body_handle) {
+                        char_body.set_translation(next_position, true);
+                    }
+                    if let Some(char_collider) = self.collider_set.get_mut(*character_collider_handle) {
+                        char_collider.set_translation(next_position);
+                    }
+                } else if let Some(char_body) = self.rigid_body_set.get_mut(char_body_handle) {
+                    char_body.set_next_kinematic_translation(next_position);
+                }
+            }
+        }
+    }
+
+    // Returns this tick's events; they stay readable through `events` until the next tick.
+    pub fn tick(&mut self) -> &[GameEvent] {
+        self.events.clear();
+        self.substeps_done = 0;
+        self.previous_positions.clear();
+        self.previous_positions.extend(self.rigid_body_set.iter().map(|(handle, body)| (handle, *body.position())));
+        let stuns = &self.stuns;
+        self.player_inputs.retain(|player_id, _| !stuns.contains_key(player_id));
+
+        if self.paused {
+            // With `freeze_players` off, cursors keep moving during a pause while physics and grabs stay frozen.
+            if !self.freeze_players {
+                self.move_players();
+                self.player_inputs.clear();
+                self.query_pipeline.update(&self.rigid_body_set, &self.collider_set);
+            }
+            return &self.events;
+        }
+
+        self.run_timeline();
+        self.apply_gravity_schedule();
+        self.dash_cooldowns.retain(|_, ticks| {
+            *ticks = ticks.saturating_sub(1);
+            *ticks > 0
+        });
+        self.stuns.retain(|_, ticks| {
+            *ticks = ticks.saturating_sub(1);
+            *ticks > 0
+        });
+        self.protections.retain(|_, ticks| {
+            *ticks = ticks.saturating_sub(1);
+            *ticks > 0
+        });
+        self.spawn_cooldowns.retain(|_, ticks| {
+            *ticks = ticks.saturating_sub(1);
+            *ticks > 0
+        });
+        self.move_players();
+        self.handle_spawn_requests();
+        self.apply_air_resistance();
+        self.apply_ropes();
+
+        // Resize held objects for players sending a scale factor
+        let mut scale_requests: Vec<(RigidBodyHandle, f32)> = self.player_inputs.iter()
+            .filter_map(|(player_id, input)| {
+                let factor = input.scale_factor?;
+                let joint = self.impulse_joint_set.get(*self.grab_joints.get(player_id)?)?;
+                Some((joint.body2, factor))
+            })
+            .collect();
+        scale_requests.sort_by_key(|(handle, _)| handle.into_raw_parts());
+        for (handle, factor) in scale_requests {
+            self.scale_body(handle, factor);
+        }
+
+        let hooks = GhostHooks { holders: self.grab_holders(), owners: self.body_owners(), ghosted: &self.ghosted, pvp: self.pvp };
+        let velocities: HashMap<RigidBodyHandle, Vector2<f32>> = self.rigid_body_set.iter()
+            .filter(|(_, body)| body.is_dynamic())
+            .map(|(handle, body)| (handle, *body.linvel()))
+            .collect();
+
+        // Run the physics simulation in substeps
+        for _ in 0..self.substeps {
+            self.physics_pipeline.step(
+                &self.gravity,
+                &self.integration_parameters,
+                &mut self.island_manager,
+                &mut self.broad_phase,
+                &mut self.narrow_phase,
+                &mut self.rigid_body_set,
+                &mut self.collider_set,
+                &mut self.impulse_joint_set,
+                &mut self.multibody_joint_set,
+                &mut self.ccd_solver,
+                None,
+                &hooks,
+                &(),
+            );
+        }
+
+        self.soften_pinned_grabs();
+        self.detect_wall_impacts(&velocities);
+        self.update_frozen_bodies();
+        self.update_sensor_overlaps();
+        self.measure_health();
+        self.apply_edge_behavior();
+
+        // Respawn players touching a hazard, unless they have only just spawned
+        let mut dead_players: Vec<(PlayerId, ColliderHandle)> = self.players.iter()
+            .filter(|(player_id, _)| !self.protections.contains_key(player_id))
+            .filter_map(|(player_id, collider_handle)| {
+                let body = self.collider_set.get(*collider_handle)
+                    .and_then(|collider| collider.parent())
+                    .and_then(|parent| self.rigid_body_set.get(parent))?;
+                Some((*player_id, self.touching_hazard(&Point2::from(*body.translation()))?))
+            })
+            .collect();
+        dead_players.sort_unstable_by_key(|(player_id, _)| *player_id);
+        for (player_id, hazard) in dead_players {
+            let killer = self.kill_credit(player_id, hazard);
+            self.respawn_player(player_id);
+            self.events.push(GameEvent::PlayerDied { player_id });
+            if let Some(killer) = killer {
+                self.add_score(killer, KILL_POINTS);
+                self.events.push(GameEvent::Kill { killer, victim: player_id });
+            }
+        }
+
+        // Handle grab logic once per frame, after physics has settled. `is_mouse_down` is where the
+        // button ended up; the edge flags say it also changed in between, so a click shorter than a
+        // tick still grabs, holding on through the next step, and a quick re-click lets go and
+        // grabs again.
+        // In id order, since grabs made this tick take joint slots in the order they are created.
+        let mut players: Vec<(PlayerId, ColliderHandle)> = self.players.iter().map(|(player_id, handle)| (*player_id, *handle)).collect();
+        players.sort_unstable_by_key(|(player_id, _)| *player_id);
+        for (player_id, character_collider_handle) in players {
+            if self.pending_releases.remove(&player_id) {
+                self.release_grab(player_id);
+            }
+            let Some(input) = self.player_inputs.get(&player_id).cloned() else {
+                continue;
+            };
+            if input.is_mouse_down && input.grab_released {
+                self.release_grab(player_id);
+            }
+            let holding = self.grab_joints.contains_key(&player_id);
+            if !holding && (input.is_mouse_down || input.grab_pressed) {
+                let char_body_handle = self.collider_set[character_collider_handle].parent().unwrap();
+                self.grab_hovered(player_id, char_body_handle);
+            } else if holding && input.is_mouse_down {
+                if let Some(charge) = self.grab_charges.get_mut(&player_id) {
+                    charge.hold_ticks += 1;
+                    charge.pull_distance += vector![input.mouse_dx, input.mouse_dy].norm();
+                }
+            }
+            if !input.is_mouse_down {
+                if !holding && self.grab_joints.contains_key(&player_id) {
+                    self.pending_releases.insert(player_id);
+                } else {
+                    self.release_grab(player_id);
+                }
+            }
+        }
+
+        self.orient_tools();
+        self.player_inputs.clear();
+        self.query_pipeline.update(&self.rigid_body_set, &self.collider_set);
+        self.tick += 1;
+        &self.events
+    }
+
+    pub fn events(&self) -> &[GameEvent] {
+        &self.events
+    }
+
+    // Debugging aid for watching the solver: runs exactly one physics substep and nothing else, so
+    // inputs, grabs, sensors and deaths are left alone. Once a frame's worth of substeps has run the
+    // tick counter advances as `tick` would; calling `tick` mid-frame starts a fresh frame.
+    pub fn substep(&mut self) {
+        let hooks = GhostHooks { holders: self.grab_holders(), owners: self.body_owners(), ghosted: &self.ghosted, pvp: self.pvp };
+        self.physics_pipeline.step(
+            &self.gravity,
+            &self.integration_parameters,
+            &mut self.island_manager,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.rigid_body_set,
+            &mut self.collider_set,
+            &mut self.impulse_joint_set,
+            &mut self.multibody_joint_set,
+            &mut self.ccd_solver,
+            None,
+            &hooks,
+            &(),
+        );
+        self.substeps_done += 1;
+        if self.substeps_done >= self.substeps {
+            self.substeps_done = 0;
+            self.tick += 1;
+        }
+        self.query_pipeline.update(&self.rigid_body_set, &self.collider_set);
+    }
+
+    pub fn substeps_remaining(&self) -> u32 {
+        self.substeps - self.substeps_done
+    }
+
+    // FNV-1a over the tick number and every dynamic body's transform and velocity, visited in
+    // handle order so that identically-stepped games produce the same value on any build.
+    pub fn state_hash(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+
+        let mut hash = FNV_OFFSET;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        };
+
+        write(&self.tick.to_le_bytes());
+
+        let mut bodies: Vec<_> = self.rigid_body_set.iter().filter(|(_, body)| body.is_dynamic()).collect();
+        bodies.sort_by_key(|(handle, _)| handle.into_raw_parts());
+        for (handle, body) in bodies {
+            let (index, generation) = handle.into_raw_parts();
+            write(&index.to_le_bytes());
+            write(&generation.to_le_bytes());
+            for value in [
+                body.translation().x,
+                body.translation().y,
+                body.rotation().angle(),
+                body.linvel().x,
+                body.linvel().y,
+                body.angvel(),
+            ] {
+                write(&value.to_bits().to_le_bytes());
+            }
+        }
+
+        hash
+    }
+
+    // Casts a ray against walls and objects (or whatever `groups` selects). This sees the world as
+    // of the last query pipeline update, i.e. the end of the previous `tick`. `object_id` matches
+    // `PhysicsObject::id` and is `None` for arena walls.
+    pub fn raycast(&self, origin: Point2<f32>, dir: Vector2<f32>, max_toi: f32, groups: Option<InteractionGroups>) -> Option<RayHit> {
+        let dir = dir.try_normalize(f32::EPSILON)?;
+        let groups = groups.unwrap_or_else(|| InteractionGroups::new(Group::ALL, (GROUP_WALLS | GROUP_PHASE_WALLS | GROUP_SQUARE).into()));
+        let ray = Ray::new(origin, dir);
+        let (handle, hit) = self.query_pipeline.cast_ray_and_get_normal(
+            &self.rigid_body_set, &self.collider_set, &ray, max_toi, true, QueryFilter::default().groups(groups),
+        )?;
+        let object_id = self.collider_set.get(handle)
+            .and_then(|collider| collider.parent())
+            .map(|parent| parent.into_raw_parts().0);
+        Some(RayHit { object_id, distance: hit.toi, normal: [hit.normal.x, hit.normal.y] })
+    }
+
+    // Ids of the objects whose colliders overlap the box between `min` and `max`, sorted and without
+    // duplicates. Like `raycast`, this sees the world as of the end of the previous `tick`.
+    pub fn objects_in_aabb(&self, min: Point2<f32>, max: Point2<f32>) -> Vec<u32> {
+        let half_extents = (max - min).abs() / 2.0;
+        let center = nalgebra::center(&min, &max);
+        let probe = Cuboid::new(half_extents);
+        let probe_pos = Isometry::translation(center.x, center.y);
+        let filter = QueryFilter::default().groups(InteractionGroups::new(Group::ALL, GROUP_SQUARE.into()));
+        let mut ids = Vec::new();
+        self.query_pipeline.intersections_with_shape(
+            &self.rigid_body_set, &self.collider_set, &probe_pos, &probe, filter,
+            |handle| {
+                if let Some(parent) = self.collider_set.get(handle).and_then(|collider| collider.parent()) {
+                    ids.push(parent.into_raw_parts().0);
+                }
+                true
+            },
+        );
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    // Reports objects that came into contact with a wall this tick. The speed is taken from before
+    // the step, since by the end of it the wall has already stopped or bounced the object.
+    fn detect_wall_impacts(&mut self, velocities: &HashMap<RigidBodyHandle, Vector2<f32>>) {
+        let wall_index = |collider: ColliderHandle| self.wall_colliders.iter().position(|wall| *wall == collider);
+        let mut touching = HashSet::new();
+        let mut impacts: Vec<(u32, usize, f32)> = Vec::new();
+        for pair in self.narrow_phase.contact_pairs().filter(|pair| pair.has_any_active_contact) {
+            let (wall, other) = match (wall_index(pair.collider1), wall_index(pair.collider2)) {
+                (Some(wall), None) => (wall, pair.collider2),
+                (None, Some(wall)) => (wall, pair.collider1),
+                _ => continue,
+            };
+            let Some(body) = self.collider_set.get(other).and_then(|collider| collider.parent()) else {
+                continue;
+            };
+            if self.hands.values().any(|hand| *hand == body) || !touching.insert((body, wall)) || self.wall_contacts.contains(&(body, wall)) {
+                continue;
+            }
+            let (Some(velocity), Some(manifold)) = (velocities.get(&body), pair.manifolds.iter().find(|manifold| !manifold.points.is_empty())) else {
+                continue;
+            };
+            let magnitude = velocity.dot(&manifold.data.normal).abs();
+            if magnitude >= WALL_IMPACT_MIN_SPEED {
+                impacts.push((body.into_raw_parts().0, wall, magnitude));
+            }
+        }
+        impacts.sort_by_key(|(object_id, wall_index, _)| (*object_id, *wall_index));
+        self.events.extend(impacts.into_iter().map(|(object_id, wall_index, magnitude)| GameEvent::WallImpact { object_id, wall_index, magnitude }));
+        self.wall_contacts = touching;
+    }
+
+    // Freezes `freeze_when_settled` objects that have slept for `FREEZE_AFTER_TICKS`, taking them
+    // out of the solver, and thaws frozen ones touched by anything moving faster than
+    // `FREEZE_WAKE_SPEED`. Cursors don't make contacts with fixed bodies, so only objects wake them.
+    fn update_frozen_bodies(&mut self) {
+        if self.settling.is_empty() && self.frozen.is_empty() {
+            return;
+        }
+        let mut woken: Vec<RigidBodyHandle> = Vec::new();
+        if !self.frozen.is_empty() {
+            let parent = |collider: ColliderHandle| self.collider_set.get(collider).and_then(|collider| collider.parent());
+            for pair in self.narrow_phase.contact_pairs().filter(|pair| pair.has_any_active_contact) {
+                let (Some(body1), Some(body2)) = (parent(pair.collider1), parent(pair.collider2)) else {
+                    continue;
+                };
+                for (frozen, other) in [(body1, body2), (body2, body1)] {
+                    let hit_hard = self.rigid_body_set.get(other).is_some_and(|body| body.linvel().norm() > FREEZE_WAKE_SPEED);
+                    if self.frozen.contains(&frozen) && hit_hard {
+                        woken.push(frozen);
+                    }
+                }
+            }
+        }
+        woken.sort_by_key(|handle| handle.into_raw_parts());
+        woken.dedup();
+        for handle in woken {
+            self.unfreeze(handle);
+        }
+
+        for (handle, ticks) in self.settling.iter_mut() {
+            if self.frozen.contains(handle) {
+                continue;
+            }
+            let Some(body) = self.rigid_body_set.get_mut(*handle) else {
+                continue;
+            };
+            *ticks = if body.is_sleeping() { *ticks + 1 } else { 0 };
+            if *ticks >= FREEZE_AFTER_TICKS {
+                body.set_body_type(RigidBodyType::Fixed, false);
+                self.frozen.insert(*handle);
+            }
+        }
+    }
+
+    fn unfreeze(&mut self, handle: RigidBodyHandle) {
+        if !self.frozen.remove(&handle) {
+            return;
+        }
+        if let Some(body) = self.rigid_body_set.get_mut(handle) {
+            body.set_body_type(RigidBodyType::Dynamic, true);
+        }
+        if let Some(ticks) = self.settling.get_mut(&handle) {
+            *ticks = 0;
+        }
+    }
+
+    fn measure_health(&mut self) {
+        let mut health = PhysicsHealth::default();
+        for (_, body) in self.rigid_body_set.iter().filter(|(_, body)| body.is_dynamic()) {
+            let speed = body.linvel().norm();
+            if speed.is_finite() && body.translation().iter().all(|value| value.is_finite()) {
+                health.max_speed = health.max_speed.max(speed);
+            } else {
+                health.non_finite_bodies += 1;
+            }
+        }
+        for pair in self.narrow_phase.contact_pairs() {
+            for point in pair.manifolds.iter().flat_map(|manifold| manifold.points.iter()) {
+                health.max_penetration = health.max_penetration.max(-point.dist);
+            }
+        }
+        if health.is_unstable() && !self.health.is_unstable() {
+            warn!("Physics became unstable at tick {}: {:?}", self.tick, health);
+        }
+        self.health = health;
+    }
+
+    // Signals an exploded stack or NaN bodies so embedders can react, e.g. with a `restart`.
+    pub fn physics_health(&self) -> PhysicsHealth {
+        self.health
+    }
+
+    fn soften_pinned_grabs(&mut self) {
+        self.softened_grabs.retain(|player_id| self.grab_joints.contains_key(player_id));
+        if self.grab_joints.is_empty() {
+            return;
+        }
+
+        let holders = self.grab_holders();
+        let held_body = |collider: ColliderHandle| self.collider_set.get(collider).and_then(|c| c.parent()).filter(|body| holders.contains_key(body));
+        let is_wall = |collider: ColliderHandle| {
+            self.collider_set.get(collider).is_some_and(|c| c.parent().and_then(|body| self.rigid_body_set.get(body)).is_none_or(|body| body.is_fixed()))
+        };
+
+        let mut pinned: HashSet<PlayerId> = HashSet::new();
+        for pair in self.narrow_phase.contact_pairs() {
+            let held = match (held_body(pair.collider1), held_body(pair.collider2)) {
+                (Some(body), None) if is_wall(pair.collider2) => body,
+                (None, Some(body)) if is_wall(pair.collider1) => body,
+                _ => continue,
+            };
+            if pair.manifolds.iter().flat_map(|manifold| manifold.points.iter()).any(|point| point.dist < -WALL_PENETRATION_SLOP) {
+                pinned.insert(holders[&held]);
+            }
+        }
+
+        let mut changes: Vec<(PlayerId, ImpulseJointHandle, GenericJoint)> = Vec::new();
+        for (player_id, joint_handle) in &self.grab_joints {
+            let soften = pinned.contains(player_id);
+            if soften == self.softened_grabs.contains(player_id) {
+                continue;
+            }
+            if let Some(joint) = self.impulse_joint_set.get(*joint_handle) {
+                let (hand_anchor, object_anchor) = (joint.data.local_anchor1(), joint.data.local_anchor2());
+                let data = if soften {
+                    SpringJointBuilder::new(0.0, SOFT_GRAB_STIFFNESS, SOFT_GRAB_DAMPING).local_anchor1(hand_anchor).local_anchor2(object_anchor).build().into()
+                } else if self.tools.contains(&joint.body2) {
+                    // The heading is lost while soft; the next cursor movement restores it.
+                    tool_joint(hand_anchor, 0.0)
+                } else {
+                    self.grab_joint(hand_anchor, object_anchor)
+                };
+                changes.push((*player_id, *joint_handle, data));
+            }
+        }
+
+        for (player_id, joint_handle, data) in changes {
+            if let Some(joint) = self.impulse_joint_set.get_mut(joint_handle) {
+                joint.data = data;
+            }
+            if !self.softened_grabs.remove(&player_id) {
+                self.softened_grabs.insert(player_id);
+            }
+        }
+    }
+
+    // Adds an invisible trigger region that objects pass through; see `sensor_overlaps`.
+    pub fn add_sensor(&mut self, center: Point2<f32>, half_width: f32, half_height: f32) -> SensorId {
+        let sensor_filter = InteractionGroups::new(GROUP_SENSORS.into(), GROUP_SQUARE.into());
+        let collider = ColliderBuilder::cuboid(half_width, half_height).translation(center.coords).sensor(true).collision_groups(sensor_filter).build();
+        let handle = self.collider_set.insert(collider);
+        let id = self.next_sensor_id;
+        self.next_sensor_id += 1;
+        self.sensors.insert(handle, id);
+        id
+    }
+
+    pub fn remove_sensor(&mut self, id: SensorId) {
+        if let Some(handle) = self.sensors.iter().find(|(_, sensor)| **sensor == id).map(|(handle, _)| *handle) {
+            self.sensors.remove(&handle);
+            self.collider_set.remove(handle, &mut self.island_manager, &mut self.rigid_body_set, true);
+            self.sensor_overlaps.retain(|(sensor, _)| *sensor != id);
+        }
+    }
+
+    // Every (sensor, object) pair overlapping as of the end of the last `tick`, sorted. Goals,
+    // buttons and zones should read this instead of running their own intersection queries.
+    pub fn sensor_overlaps(&self) -> &[(SensorId, BodyId)] {
+        &self.sensor_overlaps
+    }
+
+    fn update_sensor_overlaps(&mut self) {
+        if self.sensors.is_empty() {
+            return;
+        }
+        let mut overlaps: Vec<(SensorId, BodyId)> = self.narrow_phase.intersection_pairs()
+            .filter(|(_, _, intersecting)| *intersecting)
+            .filter_map(|(a, b, _)| {
+                let (sensor, other) = match self.sensors.get(&a) {
+                    Some(sensor) => (*sensor, b),
+                    None => (*self.sensors.get(&b)?, a),
+                };
+                let body = self.collider_set.get(other)?.parent()?;
+                Some((sensor, body.into_raw_parts().0))
+            })
+            .collect();
+        overlaps.sort_unstable();
+        overlaps.dedup();
+
+        for &(sensor_id, object_id) in &overlaps {
+            if self.sensor_overlaps.binary_search(&(sensor_id, object_id)).is_err() {
+                self.events.push(GameEvent::SensorEntered { sensor_id, object_id });
+            }
+        }
+        for &(sensor_id, object_id) in &self.sensor_overlaps {
+            if overlaps.binary_search(&(sensor_id, object_id)).is_err() {
+                self.events.push(GameEvent::SensorExited { sensor_id, object_id });
+            }
+        }
+        self.sensor_overlaps = overlaps;
+    }
+
+    // When several grabbable objects overlap the point, the one on the highest `z_layer` wins, then
+    // the one whose center is nearest; handle order breaks any remaining tie.
+    //
+    // The cursor is the player's body today, so `point` always equals `player_pos`; the reach check
+    // is there for modes that separate the two.
+    fn grabbable_at(&self, player_pos: &Point2<f32>, point: &Point2<f32>) -> Option<RigidBodyHandle> {
+        if self.max_grab_distance.is_some_and(|max| (point - player_pos).norm() > max) {
+            return None;
+        }
+        // Any membership, so objects that cursors pass through (`player_collides: false`) can still be grabbed.
+        let grab_filter = QueryFilter::default().groups(InteractionGroups::new(Group::ALL, GROUP_SQUARE.into()));
+        let mut candidates: Vec<(RigidBodyHandle, f32)> = Vec::new();
+
+        self.query_pipeline.intersections_with_point(
+            &self.rigid_body_set, &self.collider_set, point, grab_filter,
+            |handle| {
+                if let Some(collider) = self.collider_set.get(handle).filter(|collider| collider.is_enabled()) {
+                    if let Some(parent) = collider.parent() {
+                        if let Some(body) = self.rigid_body_set.get(parent) {
+                            if body.user_data == GRABBABLE_USER_DATA {
+                                let distance = (collider.position().translation.vector - point.coords).norm_squared();
+                                candidates.push((parent, distance));
+                            }
+                        }
+                    }
+                }
+                true
+            },
+        );
+
+        let z_layer = |handle: &RigidBodyHandle| self.render_data.get(handle).and_then(|r| r.z_layer).unwrap_or(0);
+        candidates
+            .into_iter()
+            .min_by(|(a, a_distance), (b, b_distance)| {
+                z_layer(b).cmp(&z_layer(a))
+                    .then(a_distance.total_cmp(b_distance))
+                    .then(a.into_raw_parts().cmp(&b.into_raw_parts()))
+            })
+            .map(|(handle, _)| handle)
+    }
+
+    // Rigid grabs pin the object to the cursor; spring grabs pull it along with a force-based
+    // spring, so heavy objects lag behind while light ones track the cursor closely.
+    fn grab_joint(&self, hand_anchor: Point2<f32>, object_anchor: Point2<f32>) -> GenericJoint {
+        match self.grab_mode {
+            GrabMode::Rigid => RevoluteJointBuilder::new()
+                .local_anchor1(hand_anchor)
+                .local_anchor2(object_anchor)
+                .build()
+                .into(),
+            GrabMode::Spring => SpringJointBuilder::new(0.0, self.grab_stiffness, self.grab_damping)
+                .local_anchor1(hand_anchor)
+                .local_anchor2(object_anchor)
+                .build()
+                .into(),
+        }
+    }
+
+    // Turns each held tool to face its holder's movement this tick. Tiny movements are ignored so
+    // the tool doesn't jitter while the cursor rests.
+    fn orient_tools(&mut self) {
+        for (player_id, input) in &self.player_inputs {
+            let movement = vector![input.mouse_dx, input.mouse_dy];
+            if movement.norm() < TOOL_TURN_THRESHOLD || self.softened_grabs.contains(player_id) {
+                continue;
+            }
+            let Some(joint) = self.grab_joints.get(player_id).and_then(|handle| self.impulse_joint_set.get_mut(*handle)) else {
+                continue;
+            };
+            if self.tools.contains(&joint.body2) {
+                let anchor = joint.data.local_frame1.translation.vector;
+                joint.data.set_local_frame1(Isometry::new(anchor, movement.y.atan2(movement.x)));
+            }
+        }
+    }
+
+    // Visits the same objects as `get_game_state` without building its vectors, for per-tick game
+    // rules on the server. Objects with several colliders are visited once per collider.
+    pub fn for_each_object(&self, mut f: impl FnMut(ObjectView)) {
+        for (handle, body) in self.rigid_body_set.iter() {
+            if body.colliders().first().is_some_and(|first| self.players.values().any(|h| h == first)) {
+                continue;
+            }
+
+            for collider_handle in body.colliders() {
+                if let Some(collider) = self.collider_set.get(*collider_handle) {
+                    let position = collider.position();
+                    f(ObjectView {
+                        id: handle.into_raw_parts().0,
+                        x: position.translation.x,
+                        y: position.translation.y,
+                        rotation: position.rotation.angle(),
+                        user_data: body.user_data,
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn for_each_player(&self, mut f: impl FnMut(PlayerView)) {
+        for (player_id, collider_handle) in &self.players {
+            if let Some(body) = self.collider_set.get(*collider_handle).and_then(|c| c.parent()).and_then(|h| self.rigid_body_set.get(h)) {
+                let position = body.translation();
+                f(PlayerView { id: *player_id, x: position.x, y: position.y, is_grabbing: self.is_grabbing(*player_id) });
+            }
+        }
+    }
+
+    pub fn get_game_state(&self) -> GameState {
+        self.game_state_at(1.0)
+    }
+
+    // For renderers drawing faster than the physics rate: bodies are blended between their pose
+    // before the last tick (`alpha` 0) and their current one (`alpha` 1). Bodies created since then
+    // are shown where they are now.
+    pub fn get_interpolated_state(&self, alpha: f32) -> GameState {
+        self.game_state_at(if alpha.is_finite() { alpha.clamp(0.0, 1.0) } else { 1.0 })
+    }
+
+    // Positions and sizes in the map format's 0..1 space: x and y are fractions of the world width
+    // and height from the bottom-left corner, and radii are fractions of the width, as in
+    // `EntityData::radius`. Gravity stays in world units.
+    pub fn get_game_state_normalized(&self) -> GameState {
+        let (width, height) = (self.world_width, self.world_height);
+        let x = |value: f32| value / width + 0.5;
+        let y = |value: f32| value / height + 0.5;
+        let mut state = self.get_game_state();
+        for player in &mut state.players {
+            player.x = x(player.x);
+            player.y = y(player.y);
+            player.hand = player.hand.map(|[hand_x, hand_y]| [x(hand_x), y(hand_y)]);
+        }
+        for object in &mut state.objects {
+            object.x = x(object.x);
+            object.y = y(object.y);
+            object.half_width = object.half_width.map(|half_width| half_width / width);
+            object.half_height = object.half_height.map(|half_height| half_height / height);
+            object.radius = object.radius.map(|radius| radius / width);
+            object.aabb = object.aabb.map(|[min_x, min_y, max_x, max_y]| [x(min_x), y(min_y), x(max_x), y(max_y)]);
+        }
+        for rope in &mut state.ropes {
+            rope.x1 = x(rope.x1);
+            rope.y1 = y(rope.y1);
+            rope.x2 = x(rope.x2);
+            rope.y2 = y(rope.y2);
+        }
+        for boundary in &mut state.boundaries {
+            boundary.x = x(boundary.x);
+            boundary.y = y(boundary.y);
+            boundary.half_width /= width;
+            boundary.half_height /= height;
+        }
+        state
+    }
+
+    fn interpolated_position(&self, handle: RigidBodyHandle, body: &RigidBody, alpha: f32) -> Isometry<f32> {
+        match self.previous_positions.get(&handle) {
+            Some(previous) if alpha < 1.0 => previous.lerp_slerp(body.position(), alpha),
+            _ => *body.position(),
+        }
+    }
+
+    fn game_state_at(&self, alpha: f32) -> GameState {
+        GameState { 
+            players: self.players_state_at(alpha), 
+            objects: self.objects_at(alpha, |_| true), 
+            boundaries: self.boundaries.clone(),
+            gravity: [self.gravity.x, self.gravity.y],
+            ropes: self.ropes.iter().filter_map(|rope| {
+                let (body1, body2) = (self.rigid_body_set.get(rope.body1)?, self.rigid_body_set.get(rope.body2)?);
+                let start = self.rope_end(body1, &self.interpolated_position(rope.body1, body1, alpha));
+                let end = self.rope_end(body2, &self.interpolated_position(rope.body2, body2, alpha));
+                Some(RopeState { x1: start.x, y1: start.y, x2: end.x, y2: end.y })
+            }).collect(),
+            unstable: self.health.is_unstable(),
+        }
+    }
+
+    // Only the death objects, for clients drawing a hazard overlay. Lethal arena walls are the
+    // `boundaries` marked `lethal`.
+    pub fn hazards(&self) -> Vec<PhysicsObject> {
+        self.objects_at(1.0, |body| body.user_data == DEATH_USER_DATA)
+    }
+
+    fn objects_at(&self, alpha: f32, include: impl Fn(&RigidBody) -> bool) -> Vec<PhysicsObject> {
+        let holders = self.grab_holders();
+        let contact_counts = if self.include_aabbs { self.contact_counts() } else { HashMap::new() };
+        let mut objects = Vec::new();
+        for (handle, body) in self.rigid_body_set.iter() {
+            if !include(body) || body.colliders().first().is_some_and(|first| self.players.values().any(|h| h == first)) {
+                continue;
+            }
+
+            for collider_handle in body.colliders() {
+                if let Some(collider) = self.collider_set.get(*collider_handle) {
+                    let (shape, half_width, half_height, radius) = match collider.shape().as_typed_shape() {
+                        TypedShape::Cuboid(c) => (ShapeType::Square, Some(c.half_extents.x), Some(c.half_extents.y), None),
+                        TypedShape::Ball(b) => (ShapeType::Circle, None, None, Some(b.radius)),
+                        _ => continue,
+                    };
+
+                    let position = match collider.position_wrt_parent() {
+                        Some(local) if alpha < 1.0 => self.interpolated_position(handle, body, alpha) * local,
+                        _ => *collider.position(),
+                    };
+                    let render = self.render_data.get(&handle);
+
+                    objects.push(PhysicsObject {
+                        id: handle.into_raw_parts().0,
+                        x: position.translation.x, 
+                        y: position.translation.y, 
+                        rotation: position.rotation.angle(),
+                        shape,
+                        user_data: body.user_data,
+                        half_width,
+                        half_height,
+                        radius,
+                        color: render.and_then(|r| r.color.clone()),
+                        z_layer: render.and_then(|r| r.z_layer),
+                        is_grabbable: body.user_data == GRABBABLE_USER_DATA,
+                        is_grabbed: holders.contains_key(&handle),
+                        grabbed_by: holders.get(&handle).copied(),
+                        enabled: collider.is_enabled(),
+                        is_tool: self.tools.contains(&handle),
+                        is_frozen: self.frozen.contains(&handle),
+                        mass: if body.is_dynamic() { body.mass() } else { 0.0 },
+                        aabb: self.include_aabbs.then(|| {
+                            let aabb = collider.compute_aabb();
+                            [aabb.mins.x, aabb.mins.y, aabb.maxs.x, aabb.maxs.y]
+                        }),
+                        contact_count: self.include_aabbs.then(|| contact_counts.get(&handle).copied().unwrap_or(0)),
+                    });
+                }
+            }
+        }
+
+        objects.iter_mut().for_each(PhysicsObject::zero_non_finite);
+        // Draw order: by `z_layer`, then id, so overlapping objects don't swap places as bodies come and go.
+        objects.sort_by_key(|object| (object.z_layer.unwrap_or(0), object.id));
+        objects
+    }
+
+    // How many touching contact pairs each body is part of, walls included.
+    fn contact_counts(&self) -> HashMap<RigidBodyHandle, u8> {
+        let mut counts: HashMap<RigidBodyHandle, u8> = HashMap::new();
+        for pair in self.narrow_phase.contact_pairs().filter(|pair| pair.has_any_active_contact) {
+            for collider in [pair.collider1, pair.collider2] {
+                if let Some(body) = self.collider_set.get(collider).and_then(|collider| collider.parent()) {
+                    let count = counts.entry(body).or_insert(0);
+                    *count = count.saturating_add(1);
+                }
+            }
+        }
+        counts
+    }
+
+    // Just the cursors, for the frequent updates sent between full snapshots.
+    pub fn get_players_state(&self) -> Vec<Player> {
+        self.players_state_at(1.0)
+    }
+
+    fn players_state_at(&self, alpha: f32) -> Vec<Player> {
+        let mut players = Vec::new();
+        for (player_id, collider_handle) in &self.players {
+            if let Some(collider) = self.collider_set.get(*collider_handle) {
+                let body_handle = collider.parent().unwrap();
+                if let Some(body) = self.rigid_body_set.get(body_handle) {
+                    let is_grabbing = self.is_grabbing(*player_id);
+                    let player_pos = body.translation();
+                    let grab_point = Point2::new(player_pos.x, player_pos.y);
+                    let is_over_grabbable = self.grabbable_at(&Point2::from(*player_pos), &grab_point).is_some();
+                    let drawn_pos = self.interpolated_position(body_handle, body, alpha).translation;
+
+                    players.push(Player {
+                        id: *player_id,
+                        x: drawn_pos.x,
+                        y: drawn_pos.y,
+                        is_grabbing,
+                        is_over_grabbable,
+                        charge: self.grab_charge(*player_id),
+                        grab_strain: self.grab_strain(*player_id),
+                        dash_cooldown: self.dash_cooldowns.get(player_id).copied().unwrap_or(0),
+                        stunned_ticks: self.stuns.get(player_id).copied().unwrap_or(0),
+                        invulnerable: self.protections.contains_key(player_id),
+                        hand: self.hands.get(player_id)
+                            .and_then(|hand| self.rigid_body_set.get(*hand))
+                            .map(|hand| [hand.translation().x, hand.translation().y]),
+                        name: self.names.get(player_id).cloned(),
+                    });
+                }
+            }
+        }
+
+        players.iter_mut().for_each(Player::zero_non_finite);
+        players
+    }
+
+    // Packs the game state into a little-endian buffer that JS can read with a `DataView`:
+    //
+    //   u32 player_count, u32 object_count
+    //   players, one array each of player_count entries:
+    //     u32 id, f32 x, f32 y, u8 flags (bit 0 grabbing, bit 1 over grabbable)
+    //   objects, one array each of object_count entries:
+    //     u32 id, f32 x, f32 y, f32 rotation,
+    //     f32 size_a (half width, or radius for circles), f32 size_b (half height, or 0 for circles),
+    //     u8 flags (bit 0 circle, bit 1 death, bit 2 grabbable, bit 3 grabbed)
+    //
+    // Arrays follow each other with no padding, so offsets are not 4-byte aligned.
+    pub fn get_state_buffer(&self) -> Vec<u8> {
+        let state = self.get_game_state();
+        let players = &state.players;
+        let objects = &state.objects;
+        let mut buffer = Vec::with_capacity(8 + players.len() * 13 + objects.len() * 25);
+
+        buffer.extend_from_slice(&(players.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&(objects.len() as u32).to_le_bytes());
+
+        players.iter().for_each(|p| buffer.extend_from_slice(&p.id.to_le_bytes()));
+        players.iter().for_each(|p| buffer.extend_from_slice(&p.x.to_le_bytes()));
+        players.iter().for_each(|p| buffer.extend_from_slice(&p.y.to_le_bytes()));
+        players.iter().for_each(|p| buffer.push((p.is_grabbing as u8) | ((p.is_over_grabbable as u8) << 1)));
+
+        objects.iter().for_each(|o| buffer.extend_from_slice(&o.id.to_le_bytes()));
+        objects.iter().for_each(|o| buffer.extend_from_slice(&o.x.to_le_bytes()));
+        objects.iter().for_each(|o| buffer.extend_from_slice(&o.y.to_le_bytes()));
+        objects.iter().for_each(|o| buffer.extend_from_slice(&o.rotation.to_le_bytes()));
+        objects.iter().for_each(|o| buffer.extend_from_slice(&o.half_width.or(o.radius).unwrap_or(0.0).to_le_bytes()));
+        objects.iter().for_each(|o| buffer.extend_from_slice(&o.half_height.unwrap_or(0.0).to_le_bytes()));
+        objects.iter().for_each(|o| {
+            let is_circle = matches!(o.shape, ShapeType::Circle);
+            let is_death = o.user_data == DEATH_USER_DATA;
+            buffer.push((is_circle as u8) | ((is_death as u8) << 1) | ((o.is_grabbable as u8) << 2) | ((o.is_grabbed as u8) << 3));
+        });
+
+        buffer
+    }
+
+    // Rebuilds a map of the scene as it stands, for editors to save what players built. Objects are
+    // written where they are now, but as maps have no rotation or velocity fields those are lost,
+    // and objects in a disabled group come back enabled. Random spawns are written as ordinary
+    // entities, ropes name their ends `object<id>`, and the timeline and gravity schedule are
+    // counted from now, the timeline keeping only events still to come. Walls come back at the
+    // arena edges: boundaries passed to `rebuild_boundaries` keep only their `phase` and `lethal`
+    // flags, on whichever side of the arena they stand.
+    pub fn export_map(&self) -> MapData {
+        let (width, height) = (self.world_width, self.world_height);
+        let normalize = |point: Vector2<f32>| (point.x / width + 0.5, point.y / height + 0.5);
+        let owners = self.body_owners();
+        let roped: HashSet<RigidBodyHandle> = self.ropes.iter().flat_map(|rope| [rope.body1, rope.body2]).collect();
+        let object_name = |handle: RigidBodyHandle| format!("object{}", handle.into_raw_parts().0);
+        let layer_name = |bit: u32| {
+            if bit == GROUP_DEFAULT_LAYER {
+                return Some(DEFAULT_LAYER_NAME.to_string());
+            }
+            self.collision_layers.get(bit.trailing_zeros().checked_sub(FIRST_NAMED_LAYER_SHIFT)? as usize).cloned()
+        };
+        let mut groups: HashMap<ColliderHandle, String> = HashMap::new();
+        for (group, colliders) in &self.entity_groups {
+            groups.extend(colliders.iter().map(|handle| (*handle, group.clone())));
+        }
+
+        let mut bodies: Vec<(RigidBodyHandle, &RigidBody)> = self.rigid_body_set.iter().filter(|(handle, _)| !owners.contains_key(handle)).collect();
+        bodies.sort_by_key(|(handle, _)| handle.into_raw_parts());
+        let mut entities = Vec::new();
+        for (handle, body) in bodies {
+            let Some(collider_handle) = body.colliders().first().copied() else {
+                continue;
+            };
+            let Some(collider) = self.collider_set.get(collider_handle) else {
+                continue;
+            };
+            let center = collider.position().translation.vector;
+            let collision_groups = collider.collision_groups();
+            let layer = collision_groups.memberships.bits() & ALL_LAYERS;
+            let mask = collision_groups.filter.bits() & ALL_LAYERS;
+            let mut entity = EntityData {
+                name: roped.contains(&handle).then(|| object_name(handle)),
+                shape: String::new(),
+                x1: None,
+                y1: None,
+                x2: None,
+                y2: None,
+                x: None,
+                y: None,
+                radius: None,
+                radius_units: None,
+                is_static: Some(body.is_fixed() && !self.frozen.contains(&handle)),
+                is_death: Some(body.user_data == DEATH_USER_DATA),
+                is_grabbable: Some(body.user_data == GRABBABLE_USER_DATA),
+                restitution: Some(collider.restitution()),
+                friction: Some(collider.friction()),
+                color: self.render_data.get(&handle).and_then(|render| render.color.clone()),
+                z_layer: self.render_data.get(&handle).and_then(|render| render.z_layer),
+                layer: (layer != GROUP_DEFAULT_LAYER).then(|| layer_name(layer)).flatten(),
+                collides_with: (mask != ALL_LAYERS).then(|| (0..32).map(|shift| 1 << shift).filter(|bit| mask & bit != 0).filter_map(layer_name).collect()),
+                player_collides: Some(collision_groups.filter.bits() & GROUP_PLAYER != 0),
+                group: groups.get(&collider_handle).cloned(),
+                is_tool: Some(self.tools.contains(&handle)),
+                freeze_when_settled: Some(self.settling.contains_key(&handle)),
+                density: Some(collider.density()),
+                gravity_scale: Some(body.gravity_scale()),
+            };
+            if let Some(cuboid) = collider.shape().as_cuboid() {
+                let (x1, y1) = normalize(center - cuboid.half_extents);
+                let (x2, y2) = normalize(center + cuboid.half_extents);
+                entity.shape = "rect".to_string();
+                (entity.x1, entity.y1, entity.x2, entity.y2) = (Some(x1), Some(y1), Some(x2), Some(y2));
+            } else if let Some(ball) = collider.shape().as_ball() {
+                let (x, y) = normalize(center);
+                entity.shape = "circle".to_string();
+                (entity.x, entity.y, entity.radius_units) = (Some(x), Some(y), Some(ball.radius));
+            } else {
+                continue;
+            }
+            entities.push(entity);
+        }
+
+        let walls_where = |flag: fn(&Boundary) -> bool| -> Vec<String> {
+            ["floor", "ceiling", "left", "right"].into_iter()
+                .filter(|name| self.boundaries.iter().any(|boundary| flag(boundary) && wall_name(boundary) == *name))
+                .map(str::to_string)
+                .collect()
+        };
+        MapData {
+            mode: (self.object_damping == (TOPDOWN_LINEAR_DAMPING, TOPDOWN_ANGULAR_DAMPING)).then(|| "topdown".to_string()),
+            bouncy_walls: Some(self.bouncy_walls),
+            phase_walls: Some(walls_where(|boundary| boundary.phase)),
+            death_walls: Some(walls_where(|boundary| boundary.lethal)),
+            grab_mode: Some(self.grab_mode),
+            grab_stiffness: Some(self.grab_stiffness),
+            grab_damping: Some(self.grab_damping),
+            gravity: Some(self.gravity),
+            gravity_schedule: self.gravity_schedule.as_ref().map(|schedule| schedule.rebased(self.tick)),
+            timeline: Some(self.timeline.iter().skip(self.next_timed_event)
+                .map(|event| TimedEvent { tick: event.tick.saturating_sub(self.tick), action: event.action.clone() })
+                .collect()),
+            dimensions: Some(DimensionsData(width, height)),
+            entities: Some(entities),
+            random_spawn: None,
+            spawn_points: Some(self.spawn_points.iter().map(|point| {
+                let (x, y) = normalize(point.coords);
+                [x, y]
+            }).collect()),
+            solver_iterations: Some(self.integration_parameters.num_solver_iterations.get()),
+            friction_iterations: Some(self.integration_parameters.num_additional_friction_iterations),
+            wall_friction: Some(self.wall_friction),
+            player_friction: Some(self.player_friction),
+            air_resistance: Some(self.air_resistance),
+            max_grab_distance: self.max_grab_distance,
+            collision_layers: Some(self.collision_layers.clone()),
+            hand_tether: Some(self.hand_tether),
+            camera: Some(self.camera),
+            player_controller: Some(self.player_controller),
+            ropes: Some(self.ropes.iter().map(|rope| RopeData {
+                from: object_name(rope.body1),
+                to: object_name(rope.body2),
+                length: rope.length,
+                max_length: Some(rope.max_length),
+                stiffness: Some(rope.stiffness),
+                damping: Some(rope.damping),
+            }).collect()),
+            player_spawns: Some(self.player_spawns),
+            shuffle_on_restart: Some(self.shuffle_on_restart),
+            edge_behavior: Some(self.edge_behavior),
+            default_density: Some(self.object_density),
+            spawn_protection_ticks: Some(self.spawn_protection_ticks),
+        }
+    }
+
+    // Serializes the world so a long-lived room can survive a server restart; see `restore`.
+    pub fn snapshot(&self) -> Result<Vec<u8>, SnapshotError> {
+        let mut snapshot = GameSnapshot {
+            version: SNAPSHOT_VERSION,
+            tick: self.tick,
+            gravity: self.gravity,
+            gravity_schedule: self.gravity_schedule.clone(),
+            timeline: self.timeline.clone(),
+            next_timed_event: self.next_timed_event,
+            integration_parameters: self.integration_parameters,
+            island_manager: self.island_manager.clone(),
+            broad_phase: self.broad_phase.clone(),
+            narrow_phase: self.narrow_phase.clone(),
+            rigid_body_set: self.rigid_body_set.clone(),
+            collider_set: self.collider_set.clone(),
+            impulse_joint_set: self.impulse_joint_set.clone(),
+            multibody_joint_set: self.multibody_joint_set.clone(),
+            boundaries: self.boundaries.clone(),
+            edge_behavior: self.edge_behavior,
+            wall_colliders: self.wall_colliders.clone(),
+            bouncy_walls: self.bouncy_walls,
+            collision_layers: self.collision_layers.clone(),
+            camera: self.camera,
+            player_controller: self.player_controller,
+            world_width: self.world_width,
+            world_height: self.world_height,
+            substeps: self.substeps,
+            grab_mode: self.grab_mode,
+            grab_stiffness: self.grab_stiffness,
+            grab_damping: self.grab_damping,
+            max_grab_distance: self.max_grab_distance,
+            hand_tether: self.hand_tether,
+            wall_friction: self.wall_friction,
+            player_friction: self.player_friction,
+            air_resistance: self.air_resistance,
+            throw_charge_ticks: self.throw_charge_ticks,
+            throw_charge_distance: self.throw_charge_distance,
+            throw_charge_boost: self.throw_charge_boost,
+            dash_distance: self.dash_distance,
+            dash_cooldown_ticks: self.dash_cooldown_ticks,
+            spawn_protection_ticks: self.spawn_protection_ticks,
+            player_spawns: self.player_spawns,
+            max_objects: self.max_objects,
+            max_dynamic_bodies: self.max_dynamic_bodies,
+            pvp: self.pvp,
+            deterministic: self.deterministic,
+            object_damping: self.object_damping,
+            object_density: self.object_density,
+            render_data: self.render_data.iter().map(|(handle, render)| (*handle, render.clone())).collect(),
+            entity_groups: self.entity_groups.iter().map(|(group, colliders)| (group.clone(), colliders.clone())).collect(),
+            tools: self.tools.iter().copied().collect(),
+            settling: self.settling.iter().map(|(handle, ticks)| (*handle, *ticks)).collect(),
+            frozen: self.frozen.iter().copied().collect(),
+            ropes: self.ropes.clone(),
+            random_spawn: self.random_spawn.clone(),
+            spawn_slots: self.spawn_slots.clone(),
+            shuffle_on_restart: self.shuffle_on_restart,
+            restarts: self.restarts,
+            spawn_points: self.spawn_points.clone(),
+            sensors: self.sensors.iter().map(|(handle, id)| (*handle, *id)).collect(),
+            next_sensor_id: self.next_sensor_id,
+        };
+        // Hash sets and maps iterate in a per-process order; sorted, equal worlds give equal bytes.
+        snapshot.render_data.sort_by_key(|(handle, _)| handle.into_raw_parts());
+        snapshot.tools.sort_by_key(|handle| handle.into_raw_parts());
+        snapshot.settling.sort_by_key(|(handle, _)| handle.into_raw_parts());
+        snapshot.frozen.sort_by_key(|handle| handle.into_raw_parts());
+        snapshot.sensors.sort_by_key(|(handle, _)| handle.into_raw_parts());
+
+        // Player cursors and hands (and the grab joints hanging off them) belong to connections, not the world.
+        let player_bodies = self.players.values().filter_map(|collider_handle| self.collider_set.get(*collider_handle).and_then(|collider| collider.parent()));
+        for body_handle in player_bodies.chain(self.hands.values().copied()) {
+            snapshot.rigid_body_set.remove(body_handle, &mut snapshot.island_manager, &mut snapshot.collider_set, &mut snapshot.impulse_joint_set, &mut snapshot.multibody_joint_set, true);
+        }
+
+        serde_json::to_vec(&snapshot).map_err(|e| SnapshotError::Malformed { reason: e.to_string() })
+    }
+
+    pub fn restore(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let header: SnapshotHeader = serde_json::from_slice(bytes).map_err(|e| SnapshotError::Malformed { reason: e.to_string() })?;
+        if header.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::VersionMismatch { found: header.version, expected: SNAPSHOT_VERSION });
+        }
+        let snapshot: GameSnapshot = serde_json::from_slice(bytes).map_err(|e| SnapshotError::Malformed { reason: e.to_string() })?;
+        if snapshot.deterministic && !Game::is_deterministic() {
+            return Err(SnapshotError::DeterminismUnavailable);
+        }
+
+        let mut game = Self::new(None);
+        game.tick = snapshot.tick;
+        game.gravity = snapshot.gravity;
+        game.gravity_schedule = snapshot.gravity_schedule;
+        game.timeline = snapshot.timeline;
+        game.next_timed_event = snapshot.next_timed_event;
+        game.integration_parameters = snapshot.integration_parameters;
+        game.island_manager = snapshot.island_manager;
+        game.broad_phase = snapshot.broad_phase;
+        game.narrow_phase = snapshot.narrow_phase;
+        game.rigid_body_set = snapshot.rigid_body_set;
+        game.collider_set = snapshot.collider_set;
+        game.impulse_joint_set = snapshot.impulse_joint_set;
+        game.multibody_joint_set = snapshot.multibody_joint_set;
+        game.boundaries = snapshot.boundaries;
+        game.edge_behavior = snapshot.edge_behavior;
+        game.wall_colliders = snapshot.wall_colliders;
+        game.bouncy_walls = snapshot.bouncy_walls;
+        game.collision_layers = snapshot.collision_layers;
+        game.camera = snapshot.camera;
+        game.set_player_controller(snapshot.player_controller);
+        game.world_width = snapshot.world_width;
+        game.world_height = snapshot.world_height;
+        game.substeps = snapshot.substeps;
+        game.grab_mode = snapshot.grab_mode;
+        game.grab_stiffness = snapshot.grab_stiffness;
+        game.grab_damping = snapshot.grab_damping;
+        game.max_grab_distance = snapshot.max_grab_distance;
+        game.hand_tether = snapshot.hand_tether;
+        game.wall_friction = snapshot.wall_friction;
+        game.player_friction = snapshot.player_friction;
+        game.air_resistance = snapshot.air_resistance;
+        game.throw_charge_ticks = snapshot.throw_charge_ticks;
+        game.throw_charge_distance = snapshot.throw_charge_distance;
+        game.throw_charge_boost = snapshot.throw_charge_boost;
+        game.dash_distance = snapshot.dash_distance;
+        game.dash_cooldown_ticks = snapshot.dash_cooldown_ticks;
+        game.spawn_protection_ticks = snapshot.spawn_protection_ticks;
+        game.player_spawns = snapshot.player_spawns;
+        game.max_objects = snapshot.max_objects;
+        game.max_dynamic_bodies = snapshot.max_dynamic_bodies;
+        game.pvp = snapshot.pvp;
+        game.deterministic = snapshot.deterministic;
+        game.object_damping = snapshot.object_damping;
+        game.object_density = snapshot.object_density;
+        game.render_data = snapshot.render_data.into_iter().collect();
+        game.entity_groups = snapshot.entity_groups.into_iter().collect();
+        game.tools = snapshot.tools.into_iter().collect();
+        game.settling = snapshot.settling.into_iter().collect();
+        game.frozen = snapshot.frozen.into_iter().collect();
+        game.ropes = snapshot.ropes;
+        game.random_spawn = snapshot.random_spawn;
+        game.spawn_slots = snapshot.spawn_slots;
+        game.shuffle_on_restart = snapshot.shuffle_on_restart;
+        game.restarts = snapshot.restarts;
+        game.spawn_points = snapshot.spawn_points;
+        game.sensors = snapshot.sensors.into_iter().collect();
+        game.next_sensor_id = snapshot.next_sensor_id;
+        game.query_pipeline.update(&game.rigid_body_set, &game.collider_set);
+        Ok(game)
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    // Puts every map object back where the map placed it. With `shuffle_on_restart` the objects
+    // trade starting places instead, dealt from the map's random spawn seed and the restart count,
+    // so every client running the same map sees the same layout.
+    pub fn restart(&mut self) {
+        let frozen: Vec<RigidBodyHandle> = self.frozen.iter().copied().collect();
+        for handle in frozen {
+            self.unfreeze(handle);
+        }
+        self.restarts += 1;
+        let mut poses: Vec<Isometry<f32>> = self.spawn_slots.iter().map(|(_, pose)| *pose).collect();
+        if self.shuffle_on_restart {
+            // Map entities all have their body at the origin and differ only in where their shape is
+            // attached, so the places dealt out are where each shape started, not each body.
+            let shape_offset = |handle: &RigidBodyHandle| self.rigid_body_set.get(*handle)
+                .and_then(|body| body.colliders().first())
+                .and_then(|collider| self.collider_set.get(*collider))
+                .and_then(|collider| collider.position_wrt_parent().copied())
+                .unwrap_or_else(Isometry::identity);
+            let mut places: Vec<Isometry<f32>> = self.spawn_slots.iter().map(|(handle, pose)| pose * shape_offset(handle)).collect();
+            let seed = self.random_spawn.as_ref().map_or(0, |spawn| spawn.seed);
+            places.shuffle(&mut StdRng::seed_from_u64(seed.wrapping_add(self.restarts)));
+            poses = self.spawn_slots.iter().zip(places).map(|((handle, _), place)| place * shape_offset(handle).inverse()).collect();
+        }
+        for ((handle, _), pose) in self.spawn_slots.iter().zip(poses) {
+            if let Some(body) = self.rigid_body_set.get_mut(*handle) {
+                body.set_position(pose, true);
+                body.set_linvel(vector![0.0, 0.0], true);
+                body.set_angvel(0.0, true);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(json: &str) -> MapData {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn input(mouse_dx: f32, mouse_dy: f32, is_mouse_down: bool) -> PlayerInput {
+        PlayerInput {
+            mouse_dx,
+            mouse_dy,
+            is_mouse_down,
+            scale_factor: None,
+            dash: false,
+            dash_dir: (0.0, 0.0),
+            grab_pressed: false,
+            grab_released: false,
+            spawn_request: None,
+            mouse_path: None,
+        }
+    }
+
+    // Map objects in handle order, i.e. in the order the map lists them.
+    fn object_handles(game: &Game) -> Vec<RigidBodyHandle> {
+        let owners = game.body_owners();
+        let mut handles: Vec<RigidBodyHandle> = game.rigid_body_set.iter()
+            .filter(|(handle, body)| !owners.contains_key(handle) && !body.colliders().is_empty())
+            .map(|(handle, _)| handle)
+            .collect();
+        handles.sort_by_key(|handle| handle.into_raw_parts());
+        handles
+    }
+
+    fn first_collider(game: &Game, handle: RigidBodyHandle) -> &Collider {
+        &game.collider_set[game.rigid_body_set[handle].colliders()[0]]
+    }
+
+    fn cursor(game: &Game, player_id: PlayerId) -> Point2<f32> {
+        let player = game.get_game_state().players.into_iter().find(|player| player.id == player_id).unwrap();
+        Point2::new(player.x, player.y)
+    }
+
+    fn grab_input() -> PlayerInput {
+        PlayerInput { grab_pressed: true, ..input(0.0, 0.0, true) }
+    }
+
+    // A still, gravity-free arena with one grabbable box around the center, where players spawn.
+    // Ticked once, since grabs only see what the query pipeline saw at the end of the last tick.
+    fn still_box_game() -> Game {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55 }
+        ] }"#)));
+        game.tick();
+        game
+    }
+
+    fn died(game: &Game, player_id: PlayerId) -> bool {
+        game.events().iter().any(|event| matches!(event, GameEvent::PlayerDied { player_id: id } if *id == player_id))
+    }
+
+    // Hazards are found with the query pipeline, which a fresh game only fills in at the end of its first tick.
+    fn dies_within(game: &mut Game, player_id: PlayerId, ticks: u32) -> bool {
+        (0..ticks).any(|_| {
+            game.tick();
+            died(game, player_id)
+        })
+    }
+
+    fn assert_json_close(a: &serde_json::Value, b: &serde_json::Value, path: &str) {
+        use serde_json::Value;
+        match (a, b) {
+            (Value::Number(x), Value::Number(y)) => {
+                assert!((x.as_f64().unwrap() - y.as_f64().unwrap()).abs() < 1e-4, "{} differs: {} vs {}", path, x, y);
+            }
+            (Value::Array(x), Value::Array(y)) => {
+                assert_eq!(x.len(), y.len(), "{} differs in length", path);
+                for (index, (x, y)) in x.iter().zip(y).enumerate() {
+                    assert_json_close(x, y, &format!("{}[{}]", path, index));
+                }
+            }
+            (Value::Object(x), Value::Object(y)) => {
+                assert_eq!(x.keys().collect::<Vec<_>>(), y.keys().collect::<Vec<_>>(), "{} differs in fields", path);
+                for (key, x) in x {
+                    assert_json_close(x, &y[key], &format!("{}.{}", path, key));
+                }
+            }
+            _ => assert_eq!(a, b, "{} differs", path),
+        }
+    }
+
+    #[test]
+    fn force_release_forgets_a_softened_grab() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        game.apply_input(1, grab_input());
+        game.tick();
+        assert!(game.is_grabbing(1));
+        game.softened_grabs.insert(1);
+
+        game.force_release(1);
+        assert!(!game.is_grabbing(1));
+        assert!(!game.softened_grabs.contains(&1));
+    }
+
+    #[test]
+    fn click_within_one_tick_still_drags_the_object() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        let object = object_handles(&game)[0];
+        game.apply_input(1, PlayerInput { grab_pressed: true, grab_released: true, ..input(0.0, 0.0, false) });
+        game.tick();
+        assert!(game.is_grabbing(1));
+
+        game.apply_input(1, input(1.0, 0.0, false));
+        game.tick();
+        assert!(!game.is_grabbing(1));
+        assert!(game.rigid_body_set[object].translation().x > 0.2);
+    }
+
+    #[test]
+    fn death_objects_kill_even_without_player_collisions() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.4, "y1": 0.4, "x2": 0.6, "y2": 0.6, "is_static": true, "is_death": true, "player_collides": false }
+        ] }"#)));
+        game.add_player(1);
+        assert!(dies_within(&mut game, 1, 2));
+    }
+
+    #[test]
+    fn lethal_phase_walls_kill() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "phase_walls": ["left"], "death_walls": ["left"],
+            "spawn_points": [[0.0, 0.5]] }"#)));
+        game.add_player(1);
+        assert!(dies_within(&mut game, 1, 2));
+    }
+
+    #[test]
+    fn protected_players_survive_hazards_until_protection_runs_out() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "phase_walls": ["left"], "death_walls": ["left"],
+            "spawn_points": [[0.0, 0.5]], "spawn_protection_ticks": 5 }"#)));
+        game.add_player(1);
+        for _ in 0..4 {
+            game.tick();
+            assert!(!died(&game, 1));
+        }
+        game.tick();
+        assert!(died(&game, 1));
+    }
+
+    #[test]
+    fn removed_players_lose_kill_credit() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        game.apply_input(1, grab_input());
+        game.tick();
+        assert!(game.last_touched_by.values().any(|(toucher, _)| *toucher == 1));
+
+        game.remove_player(1);
+        assert!(game.last_touched_by.is_empty());
+    }
+
+    #[test]
+    fn exported_maps_load_back_to_the_same_map() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, -1.0], "phase_walls": ["left"], "death_walls": ["floor"],
+            "gravity_schedule": { "type": "keyframes", "keyframes": [[0, [0.0, -1.0]], [90, [0.5, 0.0]]], "period": 200 },
+            "timeline": [{ "tick": 30, "type": "set_gravity", "gravity": [0.0, 1.0] }, { "tick": 500, "type": "set_gravity", "gravity": [0.0, -2.0] }],
+            "spawn_points": [[0.25, 0.5]],
+            "entities": [
+                { "shape": "rect", "x1": 0.1, "y1": 0.1, "x2": 0.2, "y2": 0.2, "color": "red" },
+                { "shape": "circle", "x": 0.6, "y": 0.7, "radius_units": 0.3, "is_death": true },
+                { "shape": "rect", "x1": 0.0, "y1": 0.0, "x2": 1.0, "y2": 0.05, "is_static": true }
+            ] }"#)));
+        for _ in 0..120 {
+            game.tick();
+        }
+        let exported = serde_json::to_value(game.export_map()).unwrap();
+        let reloaded = Game::new(Some(serde_json::from_value(exported.clone()).unwrap()));
+        let reexported = serde_json::to_value(reloaded.export_map()).unwrap();
+        assert_json_close(&exported, &reexported, "map");
+        assert_eq!(exported["timeline"].as_array().unwrap().len(), 1);
+        assert_eq!(exported["timeline"][0]["tick"], 380);
+    }
+
+    #[test]
+    fn exported_gravity_schedules_pick_up_where_the_game_is() {
+        let schedules = [
+            GravitySchedule::Keyframes { keyframes: vec![(0, vector![0.0, -1.0]), (100, vector![1.0, 0.0]), (300, vector![0.0, 1.0])], period: None },
+            GravitySchedule::Keyframes { keyframes: vec![(0, vector![0.0, -1.0]), (100, vector![1.0, 0.0]), (300, vector![0.0, 1.0])], period: Some(400) },
+            GravitySchedule::Keyframes { keyframes: vec![(50, vector![1.0, 0.0])], period: None },
+            GravitySchedule::Sine { base: vector![0.0, -2.0], amplitude: vector![1.0, 0.5], period: 300, offset: 40 },
+        ];
+        for schedule in schedules {
+            for now in [0, 10, 137, 999] {
+                let rebased = schedule.rebased(now);
+                for tick in 0..1000 {
+                    assert_eq!(rebased.gravity_at(tick), schedule.gravity_at(now + tick), "{:?} from {} at {}", schedule, now, tick);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exported_walls_follow_rebuilt_boundaries() {
+        let mut game = Game::new(None);
+        game.rebuild_boundaries(vec![
+            Boundary { x: 4.0, y: 0.0, half_width: 0.1, half_height: 3.0, phase: true, lethal: false },
+            Boundary { x: 0.0, y: -3.0, half_width: 4.0, half_height: 0.1, phase: false, lethal: true },
+        ]);
+        let exported = game.export_map();
+        assert_eq!(exported.phase_walls, Some(vec!["right".to_string()]));
+        assert_eq!(exported.death_walls, Some(vec!["floor".to_string()]));
+    }
+
+    #[test]
+    fn out_of_range_restitution_and_friction_are_clamped() {
+        let game = Game::new(Some(map(r#"{ "entities": [
+            { "shape": "circle", "x": 0.5, "y": 0.5, "restitution": 5.0, "friction": -1.0 },
+            { "shape": "circle", "x": 0.2, "y": 0.5, "restitution": -2.0, "friction": 1e39 }
+        ] }"#)));
+        let objects = object_handles(&game);
+        assert_eq!(first_collider(&game, objects[0]).restitution(), MAX_RESTITUTION);
+        assert_eq!(first_collider(&game, objects[0]).friction(), 0.0);
+        assert_eq!(first_collider(&game, objects[1]).restitution(), MIN_RESTITUTION);
+        assert_eq!(first_collider(&game, objects[1]).friction(), 0.0);
+    }
+
+    #[test]
+    fn roped_bodies_stay_within_max_length() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, -9.81], "entities": [
+            { "name": "hook", "shape": "rect", "x1": 0.48, "y1": 0.8, "x2": 0.52, "y2": 0.85, "is_static": true },
+            { "name": "weight", "shape": "rect", "x1": 0.48, "y1": 0.65, "x2": 0.52, "y2": 0.7 }
+        ], "ropes": [{ "from": "hook", "to": "weight", "length": 1.0, "max_length": 1.5 }] }"#)));
+        let (hook, weight) = (object_handles(&game)[0], object_handles(&game)[1]);
+        let center = |game: &Game, handle| first_collider(game, handle).position().translation.vector;
+        for _ in 0..180 {
+            game.tick();
+            assert!((center(&game, weight) - center(&game, hook)).norm() < 1.5 + 0.05);
+        }
+
+        let rope = &game.get_game_state().ropes[0];
+        assert!((vector![rope.x1, rope.y1] - center(&game, hook)).norm() < 1e-4);
+        assert!((vector![rope.x2, rope.y2] - center(&game, weight)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn shuffled_restarts_match_for_the_same_seed() {
+        let shuffled = || {
+            let entities: Vec<String> = (0..8).map(|i| {
+                let x = 0.1 + i as f32 * 0.1;
+                format!(r#"{{ "shape": "rect", "x1": {}, "y1": 0.45, "x2": {}, "y2": 0.5 }}"#, x, x + 0.04)
+            }).collect();
+            Game::new(Some(map(&format!(r#"{{ "gravity": [0.0, 0.0], "shuffle_on_restart": true, "entities": [{}] }}"#, entities.join(", ")))))
+        };
+        let layout = |game: &Game| -> Vec<(f32, f32)> {
+            object_handles(game).into_iter().map(|handle| {
+                let position = first_collider(game, handle).position().translation;
+                (position.x, position.y)
+            }).collect()
+        };
+        let (mut first, mut second) = (shuffled(), shuffled());
+        let original = layout(&first);
+        for game in [&mut first, &mut second] {
+            game.restart();
+            game.tick();
+        }
+
+        assert_eq!(layout(&first), layout(&second));
+        assert_ne!(layout(&first), original);
+        let mut places = layout(&first);
+        places.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (place, start) in places.iter().zip(&original) {
+            assert!((place.0 - start.0).abs() < 1e-4 && (place.1 - start.1).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn wrapped_objects_come_back_on_the_other_side() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "edge_behavior": "wrap", "entities": [
+            { "shape": "rect", "x1": 0.9, "y1": 0.45, "x2": 0.95, "y2": 0.55 }
+        ] }"#)));
+        let square = object_handles(&game)[0];
+        game.rigid_body_set[square].set_linvel(vector![5.0, 0.0], true);
+        let x = |game: &Game| first_collider(game, square).position().translation.x;
+        let mut last_x = x(&game);
+        let mut wrapped = false;
+        for _ in 0..60 {
+            game.tick();
+            wrapped |= x(&game) < last_x;
+            last_x = x(&game);
+            assert!(last_x.abs() <= 8.0 + 0.1);
+        }
+        assert!(wrapped && last_x < 0.0);
+        assert!(game.rigid_body_set[square].linvel().x > 0.0);
+        assert!(first_collider(&game, square).position().translation.y.abs() < 1e-3);
+    }
+
+    #[test]
+    fn pushed_objects_keep_gliding_in_topdown_mode() {
+        let mut game = Game::new(Some(map(r#"{ "mode": "topdown", "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55 }
+        ] }"#)));
+        let square = object_handles(&game)[0];
+        let start = first_collider(&game, square).translation().x;
+        game.rigid_body_set[square].set_linvel(vector![2.0, 0.0], true);
+        for _ in 0..60 {
+            game.tick();
+        }
+        let velocity = *game.rigid_body_set[square].linvel();
+        assert!(velocity.x > 1.8, "slowed to {}", velocity.x);
+        assert!(velocity.y.abs() < 1e-4);
+        assert!(first_collider(&game, square).translation().x - start > 1.8);
+    }
+
+    #[test]
+    fn grab_query_skips_parentless_colliders() {
+        let mut game = still_box_game();
+        let loose = ColliderBuilder::cuboid(1.0, 1.0).sensor(true).collision_groups(InteractionGroups::new(GROUP_SQUARE.into(), Group::ALL)).build();
+        game.collider_set.insert(loose);
+        game.tick();
+
+        game.add_player(1);
+        game.apply_input(1, grab_input());
+        game.tick();
+        assert!(game.is_grabbing(1));
+    }
+
+    #[test]
+    fn spring_grabs_let_heavy_objects_trail_the_cursor() {
+        let lag_behind_cursor = |density: f32| {
+            let mut game = Game::new(Some(map(&format!(r#"{{ "gravity": [0.0, 0.0], "grab_mode": "spring", "entities": [
+                {{ "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55, "density": {} }}
+            ] }}"#, density))));
+            let square = object_handles(&game)[0];
+            game.add_player(1);
+            game.tick();
+            game.apply_input(1, grab_input());
+            game.tick();
+            assert!(game.is_grabbing(1));
+            let cursor_start = cursor(&game, 1).x;
+            let square_start = first_collider(&game, square).translation().x;
+            for _ in 0..20 {
+                game.apply_input(1, input(0.05, 0.0, true));
+                game.tick();
+            }
+            let cursor_moved = cursor(&game, 1).x - cursor_start;
+            assert!(cursor_moved > 0.5);
+            cursor_moved - (first_collider(&game, square).translation().x - square_start)
+        };
+        let light = lag_behind_cursor(1.0);
+        let heavy = lag_behind_cursor(50.0);
+        assert!(heavy > light * 2.0, "heavy lag {} vs light lag {}", heavy, light);
+        assert!(light < 0.5, "light object lagged {}", light);
+    }
+
+    #[test]
+    fn phase_walls_let_cursors_through_but_not_objects() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "phase_walls": ["right"], "entities": [
+            { "shape": "rect", "x1": 0.85, "y1": 0.45, "x2": 0.9, "y2": 0.55 }
+        ] }"#)));
+        game.tick();
+        let square = object_handles(&game)[0];
+        game.rigid_body_set[square].set_linvel(vector![20.0, 0.0], true);
+        game.add_player(1);
+        for _ in 0..30 {
+            game.apply_input(1, input(0.4, 0.0, false));
+            game.tick();
+        }
+        assert!(cursor(&game, 1).x > game.world_width / 2.0);
+        assert!(first_collider(&game, square).position().translation.x < game.world_width / 2.0);
+    }
+
+    #[test]
+    fn nearer_of_two_overlapping_objects_is_grabbed() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.4, "y1": 0.4, "x2": 0.8, "y2": 0.6, "is_static": true },
+            { "shape": "rect", "x1": 0.48, "y1": 0.45, "x2": 0.56, "y2": 0.55, "is_static": true }
+        ] }"#)));
+        game.tick();
+        let near = object_handles(&game)[1];
+        game.add_player(1);
+        game.apply_input(1, grab_input());
+        game.tick();
+        assert_eq!(game.impulse_joint_set.get(game.grab_joints[&1]).unwrap().body2, near);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_random_layout() {
+        let layout = |seed: u64| {
+            let game = Game::new(Some(map(&format!(r#"{{ "random_spawn": {{ "count": 12, "seed": {} }} }}"#, seed))));
+            serde_json::to_string(&game.get_game_state()).unwrap()
+        };
+        assert_eq!(layout(7), layout(7));
+        assert_ne!(layout(7), layout(8));
+    }
+
+    #[test]
+    fn unusable_random_spawn_regions_and_sizes_are_rejected() {
+        // 1e39 is past f32's range, so it reads as infinity.
+        for (fields, field) in [(r#""region": [0.0, 0.0, 1e39, 1.0]"#, "region"), (r#""region": [-0.5, 0.0, 1.0, 1.0]"#, "region"), (r#""size": 0.0"#, "size"), (r#""size": 1e39"#, "size")] {
+            let data = map(&format!(r#"{{ "random_spawn": {{ "count": 3, "seed": 1, {} }} }}"#, fields));
+            assert_eq!(Game::try_new(Some(data)).err(), Some(GameInitError::InvalidRandomSpawn { field }));
+        }
+
+        let mut data = map(r#"{ "random_spawn": { "count": 3, "seed": 1 } }"#);
+        data.random_spawn.as_mut().unwrap().region = Some([f32::NAN, 0.0, 1.0, 1.0]);
+        assert_eq!(data.validate().err(), Some(GameInitError::InvalidRandomSpawn { field: "region" }));
+        // `new` leaves the spawn out rather than panicking on it.
+        assert!(Game::new(Some(data)).get_game_state().objects.is_empty());
+    }
+
+    #[test]
+    fn state_buffer_matches_the_game_state() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55 },
+            { "shape": "circle", "x": 0.2, "y": 0.3, "radius": 0.05, "is_death": true }
+        ] }"#)));
+        game.add_player(1);
+        game.add_player(2);
+        game.tick();
+        game.apply_input(1, grab_input());
+        game.tick();
+
+        let state = game.get_game_state();
+        let buffer = game.get_state_buffer();
+        let word = |index: usize| <[u8; 4]>::try_from(&buffer[index * 4..index * 4 + 4]).unwrap();
+        let (players, objects) = (state.players.len(), state.objects.len());
+        assert_eq!((u32::from_le_bytes(word(0)), u32::from_le_bytes(word(1))), (2, 2));
+        assert_eq!(buffer.len(), 8 + players * 13 + objects * 25);
+
+        for (i, player) in state.players.iter().enumerate() {
+            assert_eq!(u32::from_le_bytes(word(2 + i)), player.id);
+            assert_eq!(f32::from_le_bytes(word(2 + players + i)), player.x);
+            assert_eq!(f32::from_le_bytes(word(2 + players * 2 + i)), player.y);
+            assert_eq!(buffer[8 + players * 12 + i], (player.is_grabbing as u8) | ((player.is_over_grabbable as u8) << 1));
+        }
+        assert!(state.players.iter().any(|player| player.is_grabbing));
+
+        let object_base = 8 + players * 13;
+        let object_word = |field: usize, i: usize| {
+            let start = object_base + (field * objects + i) * 4;
+            <[u8; 4]>::try_from(&buffer[start..start + 4]).unwrap()
+        };
+        for (i, object) in state.objects.iter().enumerate() {
+            assert_eq!(u32::from_le_bytes(object_word(0, i)), object.id);
+            assert_eq!(f32::from_le_bytes(object_word(1, i)), object.x);
+            assert_eq!(f32::from_le_bytes(object_word(2, i)), object.y);
+            assert_eq!(f32::from_le_bytes(object_word(3, i)), object.rotation);
+            assert_eq!(f32::from_le_bytes(object_word(4, i)), object.half_width.or(object.radius).unwrap());
+            assert_eq!(f32::from_le_bytes(object_word(5, i)), object.half_height.unwrap_or(0.0));
+            let flags = buffer[object_base + objects * 24 + i];
+            assert_eq!(flags & 1 == 1, matches!(object.shape, ShapeType::Circle));
+            assert_eq!(flags & 2 == 2, object.user_data == DEATH_USER_DATA);
+            assert_eq!(flags & 4 == 4, object.is_grabbable);
+            assert_eq!(flags & 8 == 8, object.is_grabbed);
+        }
+        assert!(state.objects.iter().any(|object| object.is_grabbed));
+    }
+
+    #[test]
+    fn players_join_at_spawn_points() {
+        let mut game = Game::new(Some(map(r#"{ "spawn_points": [[0.25, 0.5], [0.75, 0.25]] }"#)));
+        for player_id in 1..=3 {
+            game.add_player(player_id);
+        }
+        let spawns = [Point2::new(-4.0, 0.0), Point2::new(4.0, -2.25), Point2::new(-4.0, 0.0)];
+        for (player_id, spawn) in (1..=3).zip(spawns) {
+            assert!((cursor(&game, player_id) - spawn).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn lethal_floor_respawns_the_player() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "death_walls": ["floor"], "spawn_points": [[0.5, 0.75]] }"#)));
+        game.tick();
+        game.add_player(1);
+        game.apply_input(1, input(0.0, -10.0, false));
+        for _ in 0..3 {
+            game.tick();
+        }
+        assert!((cursor(&game, 1) - Point2::new(0.0, 2.25)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn cursors_move_while_paused() {
+        let mut game = Game::new(None);
+        game.add_player(1);
+        game.tick();
+        let objects_before = serde_json::to_string(&game.get_game_state().objects).unwrap();
+        game.freeze_players = false;
+        game.pause();
+        game.apply_input(1, input(1.0, 0.5, false));
+        game.tick();
+        assert!((cursor(&game, 1) - Point2::new(1.0, 0.5)).norm() < 1e-3);
+        assert_eq!(serde_json::to_string(&game.get_game_state().objects).unwrap(), objects_before);
+    }
+
+    #[test]
+    fn rotated_rects_report_their_rotated_bounds() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55 }
+        ] }"#)));
+        let rect = object_handles(&game)[0];
+        game.rigid_body_set[rect].set_rotation(Rotation::new(std::f32::consts::FRAC_PI_4), true);
+        game.tick();
+        assert_eq!(game.get_game_state().objects[0].aabb, None);
+
+        // A 1.6 x 0.9 rect turned 45 degrees about the arena center.
+        game.include_aabbs = true;
+        let aabb = game.get_game_state().objects[0].aabb.unwrap();
+        let reach = (0.8 + 0.45) * std::f32::consts::FRAC_1_SQRT_2;
+        for (got, expected) in aabb.iter().zip([-reach, -reach, reach, reach]) {
+            assert!((got - expected).abs() < 1e-3, "{:?}", aabb);
+        }
+    }
+
+    #[test]
+    fn solver_iterations_reach_the_integration_parameters() {
+        let game = Game::builder().solver_iterations(3).friction_iterations(2).build().unwrap();
+        assert_eq!(game.integration_parameters.num_solver_iterations.get(), 3);
+        assert_eq!(game.integration_parameters.num_additional_friction_iterations, 2);
+
+        let mut game = Game::new(Some(map(r#"{ "solver_iterations": 12, "friction_iterations": 6 }"#)));
+        assert_eq!(game.integration_parameters.num_solver_iterations.get(), 12);
+        assert_eq!(game.integration_parameters.num_additional_friction_iterations, 6);
+
+        game.set_solver_iterations(0);
+        game.set_friction_iterations(0);
+        assert_eq!(game.integration_parameters.num_solver_iterations.get(), 1);
+        assert_eq!(game.integration_parameters.num_additional_friction_iterations, 1);
+
+        assert_eq!(Game::try_new(Some(map(r#"{ "solver_iterations": 0 }"#))).err(), Some(GameInitError::InvalidSolverIterations { field: "solver_iterations" }));
+    }
+
+    #[test]
+    fn longer_holds_throw_harder() {
+        let throw_speed = |hold_ticks: u32| {
+            let mut game = still_box_game();
+            let object = object_handles(&game)[0];
+            game.add_player(1);
+            game.apply_input(1, grab_input());
+            game.tick();
+            for _ in 0..hold_ticks {
+                game.apply_input(1, input(0.05, 0.0, true));
+                game.tick();
+            }
+            game.apply_input(1, input(0.05, 0.0, false));
+            game.tick();
+            assert!(!game.is_grabbing(1));
+            game.rigid_body_set[object].linvel().x
+        };
+        assert!(throw_speed(40) > throw_speed(2) * 1.2);
+    }
+
+    #[test]
+    fn merged_maps_combine_entities_and_override_fields() {
+        let base = map(r#"{ "gravity": [0.0, -2.0], "bouncy_walls": true, "entities": [
+            { "name": "a", "shape": "rect", "x1": 0.1, "y1": 0.1, "x2": 0.2, "y2": 0.2 },
+            { "shape": "circle", "x": 0.5, "y": 0.5 }
+        ] }"#);
+        let layer = map(r#"{ "gravity": [0.0, 3.0], "entities": [{ "name": "b", "shape": "rect", "x1": 0.7, "y1": 0.7, "x2": 0.8, "y2": 0.8 }] }"#);
+        let merged = base.merge(layer).unwrap();
+        assert_eq!(merged.gravity, Some(vector![0.0, 3.0]));
+        assert_eq!(merged.bouncy_walls, Some(true));
+        let game = Game::new(Some(merged));
+        assert_eq!(game.object_count(), 3);
+        assert_eq!(game.gravity, vector![0.0, 3.0]);
+
+        let clash = map(r#"{ "entities": [{ "name": "a", "shape": "circle", "x": 0.3, "y": 0.3 }] }"#);
+        let base = map(r#"{ "entities": [{ "name": "a", "shape": "circle", "x": 0.6, "y": 0.6 }] }"#);
+        assert_eq!(base.merge(clash).err(), Some(GameInitError::DuplicateEntityName { name: "a".to_string() }));
+    }
+
+    #[test]
+    fn raycast_hits_the_square_in_its_path() {
+        let mut game = Game::new(Some(map(r#"{ "entities": [
+            { "shape": "rect", "x1": 0.6, "y1": 0.45, "x2": 0.7, "y2": 0.55, "is_static": true }
+        ] }"#)));
+        game.tick();
+        let square = object_handles(&game)[0];
+        let hit = game.raycast(Point2::origin(), vector![1.0, 0.0], 100.0, None).unwrap();
+        assert_eq!(hit.object_id, Some(square.into_raw_parts().0));
+        assert!((hit.distance - 1.6).abs() < 1e-4);
+        assert_eq!(hit.normal, [-1.0, 0.0]);
+
+        let wall = game.raycast(Point2::origin(), vector![-1.0, 0.0], 100.0, None).unwrap();
+        assert_eq!(wall.object_id, None);
+        assert!((wall.distance - 7.9).abs() < 1e-4);
+    }
+
+    #[test]
+    fn scaling_a_held_square_grows_it() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        game.apply_input(1, grab_input());
+        game.tick();
+        game.apply_input(1, PlayerInput { scale_factor: Some(2.0), ..input(0.0, 0.0, true) });
+        game.tick();
+        let state = game.get_game_state();
+        let square = &state.objects[0];
+        assert!((square.half_width.unwrap() - 1.6).abs() < 1e-4);
+        assert!((square.half_height.unwrap() - 0.9).abs() < 1e-4);
+        assert!(game.is_grabbing(1));
+    }
+
+    #[test]
+    fn non_grabbable_objects_collide_but_cannot_be_grabbed() {
+        let mut game = Game::new(Some(map(r#"{ "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55, "is_grabbable": false }
+        ] }"#)));
+        game.tick();
+        let square = object_handles(&game)[0];
+        game.add_player(1);
+        game.apply_input(1, grab_input());
+        game.tick();
+        assert!(!game.is_grabbing(1));
+
+        for _ in 0..300 {
+            game.tick();
+        }
+        let resting = first_collider(&game, square).position().translation.y;
+        assert!((resting - (-4.4 + 0.45)).abs() < 0.05);
+    }
+
+    #[test]
+    fn ghosted_players_drag_objects_through_each_other() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "spawn_points": [[0.3, 0.5], [0.7, 0.5]], "entities": [
+            { "shape": "rect", "x1": 0.28, "y1": 0.45, "x2": 0.32, "y2": 0.55 },
+            { "shape": "rect", "x1": 0.68, "y1": 0.45, "x2": 0.72, "y2": 0.55 }
+        ] }"#)));
+        game.tick();
+        let (dragged, held) = (object_handles(&game)[0], object_handles(&game)[1]);
+        game.add_player(1);
+        game.add_player(2);
+        game.set_ignore(1, 2, true);
+        game.apply_input(1, grab_input());
+        game.apply_input(2, grab_input());
+        game.tick();
+        assert!(game.is_grabbing(1) && game.is_grabbing(2));
+
+        for _ in 0..82 {
+            game.apply_input(1, input(0.1, 0.0, true));
+            game.apply_input(2, input(0.0, 0.0, true));
+            game.tick();
+        }
+        let center = |handle| first_collider(&game, handle).position().translation.vector;
+        assert!((center(dragged) - vector![5.0, 0.0]).norm() < 0.2);
+        assert!((center(held) - vector![3.2, 0.0]).norm() < 0.1);
+    }
+
+    #[test]
+    fn circles_sized_in_world_units_keep_their_radius_on_wide_maps() {
+        let game = Game::new(Some(map(r#"{ "dimensions": [32.0, 9.0], "entities": [
+            { "shape": "circle", "x": 0.25, "y": 0.5, "radius_units": 0.5 },
+            { "shape": "circle", "x": 0.75, "y": 0.5, "radius": 0.05 }
+        ] }"#)));
+        let radii: Vec<f32> = game.get_game_state().objects.iter().map(|object| object.radius.unwrap()).collect();
+        assert_eq!(radii.len(), 2);
+        assert!((radii[0] - 0.5).abs() < 1e-6);
+        assert!((radii[1] - 1.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sensor_overlaps_follow_a_body_passing_through() {
+        let mut game = Game::new(Some(map(r#"{ "mode": "topdown", "bouncy_walls": false, "entities": [
+            { "shape": "rect", "x1": 0.1, "y1": 0.45, "x2": 0.15, "y2": 0.55 }
+        ] }"#)));
+        let square = object_handles(&game)[0];
+        let square_id = square.into_raw_parts().0;
+        let sensor = game.add_sensor(Point2::origin(), 1.0, 1.0);
+        game.rigid_body_set[square].set_linvel(vector![10.0, 0.0], true);
+
+        let (mut entered, mut exited) = (None, None);
+        for tick in 0..60 {
+            game.tick();
+            if game.events().contains(&GameEvent::SensorEntered { sensor_id: sensor, object_id: square_id }) {
+                entered = Some(tick);
+            }
+            if game.events().contains(&GameEvent::SensorExited { sensor_id: sensor, object_id: square_id }) {
+                exited = Some(tick);
+            }
+            let inside = entered.is_some() && exited.is_none();
+            assert_eq!(game.sensor_overlaps() == [(sensor, square_id)], inside, "tick {}", tick);
+        }
+        assert!(entered.unwrap() < exited.unwrap());
+    }
+
+    #[test]
+    fn lower_wall_friction_slides_down_walls_faster() {
+        let ticks_to_floor = |wall_friction: f32| {
+            let mut game = Game::new(Some(map(&format!(r#"{{ "gravity": [-3.0, -2.0], "wall_friction": {}, "entities": [
+                {{ "shape": "rect", "x1": 0.01, "y1": 0.85, "x2": 0.05, "y2": 0.9, "friction": 0.0 }}
+            ] }}"#, wall_friction))));
+            let square = object_handles(&game)[0];
+            (0..900).find(|_| {
+                game.tick();
+                first_collider(&game, square).position().translation.y < -3.5
+            })
+        };
+        let slippery = ticks_to_floor(0.0).unwrap();
+        assert!(ticks_to_floor(1.0).is_none_or(|sticky| sticky > slippery));
+    }
+
+    #[test]
+    fn non_finite_positions_serialize_as_zero() {
+        let mut game = still_box_game();
+        let square = object_handles(&game)[0];
+        let collider = game.rigid_body_set[square].colliders()[0];
+        game.collider_set[collider].set_translation(vector![f32::NAN, f32::INFINITY]);
+
+        let json = serde_json::to_string(&game.get_game_state()).unwrap();
+        let state: GameState = serde_json::from_str(&json).unwrap();
+        assert!(state.objects.iter().all(|object| object.x.is_finite() && object.y.is_finite()));
+        assert_eq!(state.objects[0].x, 0.0);
+    }
+
+    #[test]
+    fn dash_moves_further_then_cools_down() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0] }"#)));
+        game.add_player(1);
+        game.tick();
+        let x = |game: &Game| game.get_game_state().players[0].x;
+        let dash = || PlayerInput { dash: true, dash_dir: (1.0, 0.0), ..input(0.1, 0.0, false) };
+
+        let start = x(&game);
+        game.apply_input(1, input(0.1, 0.0, false));
+        game.tick();
+        let walked = x(&game) - start;
+
+        let start = x(&game);
+        game.apply_input(1, dash());
+        game.tick();
+        assert!(x(&game) - start > walked + DEFAULT_DASH_DISTANCE * 0.9);
+        assert!(game.get_game_state().players[0].dash_cooldown > 0);
+
+        let start = x(&game);
+        game.apply_input(1, dash());
+        game.tick();
+        assert!((x(&game) - start - walked).abs() < 1e-3);
+    }
+
+    #[test]
+    fn builder_substeps_take_effect() {
+        let built = Game::builder().substeps(4).build().unwrap();
+        let default = Game::new(None);
+        assert_eq!(built.substeps, 4);
+        assert!((built.integration_parameters.dt * 4.0 - default.integration_parameters.dt * default.substeps as f32).abs() < 1e-7);
+    }
+
+    #[test]
+    fn builder_dynamic_body_limit_caps_maps_and_spawns() {
+        let squares = || map(r#"{ "entities": [
+            { "shape": "rect", "x1": 0.0, "y1": 0.0, "x2": 1.0, "y2": 0.05, "is_static": true },
+            { "shape": "rect", "x1": 0.1, "y1": 0.1, "x2": 0.2, "y2": 0.2 },
+            { "shape": "rect", "x1": 0.3, "y1": 0.1, "x2": 0.4, "y2": 0.2 }
+        ] }"#);
+        assert_eq!(Game::builder().map(squares()).max_dynamic_bodies(1).build().err(), Some(GameInitError::TooManyDynamicBodies { count: 2, max: 1 }));
+
+        let mut game = Game::builder().map(squares()).max_dynamic_bodies(3).build().unwrap();
+        game.add_player(1);
+        assert_eq!(game.dynamic_body_count(), 2);
+        assert!(game.spawn_object("rect", 0.2, Point2::origin()).is_some());
+        assert!(game.spawn_object("rect", 0.2, Point2::origin()).is_none());
+    }
+
+    #[test]
+    fn builder_pvp_off_lets_held_objects_pass_each_other() {
+        let data = map(r#"{ "gravity": [0.0, 0.0], "spawn_points": [[0.3, 0.5], [0.7, 0.5]], "entities": [
+            { "shape": "rect", "x1": 0.28, "y1": 0.45, "x2": 0.32, "y2": 0.55 },
+            { "shape": "rect", "x1": 0.68, "y1": 0.45, "x2": 0.72, "y2": 0.55 }
+        ] }"#);
+        let mut game = Game::builder().map(data).pvp(false).build().unwrap();
+        game.tick();
+        let (dragged, held) = (object_handles(&game)[0], object_handles(&game)[1]);
+        game.add_player(1);
+        game.add_player(2);
+        game.apply_input(1, grab_input());
+        game.apply_input(2, grab_input());
+        game.tick();
+        assert!(game.is_grabbing(1) && game.is_grabbing(2));
+
+        for _ in 0..82 {
+            game.apply_input(1, input(0.1, 0.0, true));
+            game.apply_input(2, input(0.0, 0.0, true));
+            game.tick();
+        }
+        let center = |handle| first_collider(&game, handle).position().translation.vector;
+        assert!((center(dragged) - vector![5.0, 0.0]).norm() < 0.2);
+        assert!((center(held) - vector![3.2, 0.0]).norm() < 0.1);
+    }
+
+    #[test]
+    fn players_state_matches_the_full_state() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        game.add_player(2);
+        game.apply_input(1, grab_input());
+        game.apply_input(2, input(1.0, -0.5, false));
+        game.tick();
+
+        let players = serde_json::to_value(game.get_players_state()).unwrap();
+        assert_eq!(players, serde_json::to_value(game.get_game_state().players).unwrap());
+        assert_eq!(players.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn grabs_beyond_max_distance_are_rejected() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "max_grab_distance": 0.5, "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55 }
+        ] }"#)));
+        game.tick();
+        let square = object_handles(&game)[0];
+        assert_eq!(game.grabbable_at(&Point2::new(0.3, 0.0), &Point2::origin()), Some(square));
+        assert_eq!(game.grabbable_at(&Point2::new(2.0, 0.0), &Point2::origin()), None);
+
+        game.add_player(1);
+        game.apply_input(1, grab_input());
+        game.tick();
+        assert!(game.is_grabbing(1));
+    }
+
+    #[test]
+    fn held_objects_report_their_holder() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        game.apply_input(1, grab_input());
+        game.tick();
+        let object = &game.get_game_state().objects[0];
+        assert!(object.is_grabbed);
+        assert_eq!(object.grabbed_by, Some(1));
+
+        game.apply_input(1, input(0.0, 0.0, false));
+        game.tick();
+        let object = &game.get_game_state().objects[0];
+        assert!(!object.is_grabbed);
+        assert_eq!(object.grabbed_by, None);
+    }
+
+    #[test]
+    fn gravity_follows_its_keyframes() {
+        let mut game = Game::new(Some(map(r#"{ "gravity_schedule": { "type": "keyframes", "keyframes": [[0, [0.0, -9.81]], [30, [0.0, 9.81]]] } }"#)));
+        for _ in 0..30 {
+            game.tick();
+        }
+        assert_eq!(game.get_game_state().gravity, [0.0, -9.81]);
+        game.tick();
+        assert_eq!(game.get_game_state().gravity, [0.0, 9.81]);
+
+        let sine = GravitySchedule::Sine { base: vector![0.0, -9.81], amplitude: vector![2.0, 0.0], period: 120, offset: 0 };
+        assert!((sine.gravity_at(30).unwrap() - vector![2.0, -9.81]).norm() < 1e-4);
+    }
+
+    #[test]
+    fn oversized_and_non_finite_maps_are_rejected() {
+        let three_squares = || map(r#"{ "entities": [
+            { "shape": "rect", "x1": 0.1, "y1": 0.1, "x2": 0.2, "y2": 0.2 },
+            { "shape": "rect", "x1": 0.3, "y1": 0.1, "x2": 0.4, "y2": 0.2 },
+            { "shape": "rect", "x1": 0.5, "y1": 0.1, "x2": 0.6, "y2": 0.2 }
+        ] }"#);
+        assert_eq!(Game::builder().map(three_squares()).max_entities(2).build().err(), Some(GameInitError::TooManyEntities { count: 3, max: 2 }));
+        assert!(Game::builder().map(three_squares()).max_entities(3).build().is_ok());
+
+        let flood = map(&format!(r#"{{ "random_spawn": {{ "count": {}, "seed": 1 }} }}"#, DEFAULT_MAX_ENTITIES + 1));
+        assert_eq!(Game::try_new(Some(flood)).err(), Some(GameInitError::TooManyEntities { count: DEFAULT_MAX_ENTITIES + 1, max: DEFAULT_MAX_ENTITIES }));
+
+        let mut poisoned = three_squares();
+        poisoned.entities.as_mut().unwrap()[1].x2 = Some(f32::NAN);
+        assert_eq!(Game::try_new(Some(poisoned)).err(), Some(GameInitError::NonFiniteField { index: 1, field: "x2" }));
+    }
+
+    #[test]
+    fn excluded_layers_pass_through_each_other_but_not_walls() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, -9.81], "collision_layers": ["red", "blue"], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.1, "x2": 0.55, "y2": 0.2, "layer": "blue", "collides_with": ["blue"] },
+            { "shape": "rect", "x1": 0.45, "y1": 0.5, "x2": 0.55, "y2": 0.6, "layer": "red", "collides_with": ["red"] }
+        ] }"#)));
+        let (blue, red) = (object_handles(&game)[0], object_handles(&game)[1]);
+        for _ in 0..300 {
+            game.tick();
+        }
+        let y = |handle| first_collider(&game, handle).position().translation.y;
+        let resting = -4.5 + 0.1 + 0.45;
+        assert!((y(blue) - resting).abs() < 0.05);
+        assert!((y(red) - resting).abs() < 0.05);
+    }
+
+    #[test]
+    fn tick_returns_grab_events() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        let object_id = object_handles(&game)[0].into_raw_parts().0;
+        game.apply_input(1, grab_input());
+        assert!(game.tick().contains(&GameEvent::Grabbed { player_id: 1, object_id }));
+        assert!(!game.tick().iter().any(|event| matches!(event, GameEvent::Grabbed { .. })));
+    }
+
+    #[test]
+    fn tethered_hand_trails_the_cursor() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "hand_tether": true }"#)));
+        game.add_player(1);
+        game.tick();
+        let positions = |game: &Game| {
+            let player = &game.get_players_state()[0];
+            (player.x, player.hand.unwrap()[0])
+        };
+
+        game.apply_input(1, input(1.0, 0.0, false));
+        game.tick();
+        let (cursor, hand) = positions(&game);
+        assert!((cursor - 1.0).abs() < 1e-3);
+        assert!(hand > 0.0 && hand < 0.5);
+
+        for _ in 0..120 {
+            game.tick();
+        }
+        let (cursor, hand) = positions(&game);
+        assert!((cursor - hand).abs() < 0.05);
+    }
+
+    #[test]
+    fn upward_gravity_lifts_objects() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, -9.81], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55 }
+        ] }"#)));
+        let square = object_handles(&game)[0];
+        let start = first_collider(&game, square).position().translation.y;
+        game.set_gravity(0.0, 9.81);
+        for _ in 0..30 {
+            game.tick();
+        }
+        assert!(first_collider(&game, square).position().translation.y > start + 0.5);
+        assert_eq!(game.get_game_state().gravity, [0.0, 9.81]);
+    }
+
+    #[test]
+    fn denser_objects_report_more_mass() {
+        let game = Game::new(Some(map(r#"{ "entities": [
+            { "shape": "rect", "x1": 0.2, "y1": 0.4, "x2": 0.3, "y2": 0.5, "density": 1.0 },
+            { "shape": "rect", "x1": 0.6, "y1": 0.4, "x2": 0.7, "y2": 0.5, "density": 4.0 }
+        ] }"#)));
+        let objects = game.get_game_state().objects;
+        assert!(objects[0].mass > 0.0);
+        assert!((objects[1].mass - objects[0].mass * 4.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn objects_dragged_into_a_wall_stay_inside() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        let square = object_handles(&game)[0];
+        game.apply_input(1, grab_input());
+        game.tick();
+        for _ in 0..120 {
+            game.apply_input(1, input(5.0, 0.0, true));
+            game.tick();
+            let x = first_collider(&game, square).position().translation.x;
+            assert!(x.is_finite() && x < 8.0 - 0.1);
+            assert!(game.rigid_body_set[square].linvel().norm() < 100.0);
+        }
+        assert!(game.is_grabbing(1));
+    }
+
+    #[test]
+    fn air_resistance_stops_pushed_objects_sooner() {
+        let speed_after_a_second = |air_resistance: f32| {
+            let mut game = Game::new(Some(map(&format!(r#"{{ "gravity": [0.0, 0.0], "air_resistance": {}, "entities": [
+                {{ "shape": "rect", "x1": 0.1, "y1": 0.45, "x2": 0.15, "y2": 0.55 }}
+            ] }}"#, air_resistance))));
+            let square = object_handles(&game)[0];
+            game.rigid_body_set[square].set_linvel(vector![4.0, 0.0], true);
+            for _ in 0..60 {
+                game.tick();
+            }
+            game.rigid_body_set[square].linvel().norm()
+        };
+        let still_air = speed_after_a_second(0.0);
+        assert!(still_air > 1.0);
+        assert!(speed_after_a_second(5.0) < still_air * 0.1);
+    }
+
+    #[test]
+    fn adding_a_player_twice_keeps_one_body() {
+        let mut game = still_box_game();
+        let (bodies, colliders) = (game.rigid_body_set.len(), game.collider_set.len());
+        assert!(game.add_player(1));
+        assert!(!game.add_player(1));
+        assert_eq!(game.rigid_body_set.len(), bodies + 1);
+        assert_eq!(game.collider_set.len(), colliders + 1);
+    }
+
+    #[test]
+    fn default_map_builds_the_configured_grid() {
+        let game = Game::builder().default_map(DefaultMapConfig { rows: 20, cols: 20, spacing: 0.4, size: 0.15 }).build().unwrap();
+        assert_eq!(game.object_count(), 400);
+        assert_eq!(Game::new(None).object_count(), 40);
+    }
+
+    #[test]
+    fn player_count_tracks_adds_and_removes() {
+        let mut game = still_box_game();
+        assert!(game.is_empty());
+        game.add_player(1);
+        game.add_player(2);
+        assert_eq!((game.player_count(), game.is_empty()), (2, false));
+        game.remove_player(1);
+        game.remove_player(7);
+        assert_eq!(game.player_count(), 1);
+        game.remove_player(2);
+        assert_eq!((game.player_count(), game.is_empty()), (0, true));
+    }
+
+    #[test]
+    fn grab_only_blocks_let_cursors_through_but_stay_grabbable() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.55, "y1": 0.45, "x2": 0.65, "y2": 0.55, "player_collides": false }
+        ] }"#)));
+        let block = object_handles(&game)[0];
+        assert!(!first_collider(&game, block).collision_groups().filter.contains(GROUP_PLAYER.into()));
+        game.add_player(1);
+        game.tick();
+
+        game.apply_input(1, input(3.0, 0.0, false));
+        game.tick();
+        assert!((game.get_players_state()[0].x - 3.0).abs() < 1e-3);
+
+        game.apply_input(1, input(-1.4, 0.0, false));
+        game.tick();
+        assert!(game.get_players_state()[0].is_over_grabbable);
+        game.apply_input(1, grab_input());
+        game.tick();
+        assert!(game.is_grabbing(1));
+    }
+
+    #[test]
+    fn rebuilt_boundaries_replace_the_wall_colliders() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, -9.81], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.6, "x2": 0.55, "y2": 0.7 }
+        ] }"#)));
+        let square = object_handles(&game)[0];
+        let shelf = Boundary { x: 0.0, y: 0.0, half_width: 8.0, half_height: 0.1, phase: false, lethal: false };
+        game.rebuild_boundaries(vec![shelf]);
+        assert_eq!(game.boundaries().len(), 1);
+        assert_eq!(game.boundaries()[0].y, 0.0);
+        assert_eq!(game.collider_set.iter().filter(|(_, collider)| collider.parent().is_none()).count(), 1);
+
+        for _ in 0..120 {
+            game.tick();
+        }
+        assert!((first_collider(&game, square).position().translation.y - 0.55).abs() < 0.05);
+    }
+
+    #[test]
+    fn sanitized_names_show_up_in_the_player_state() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        game.set_player_name(1, "  Ada\u{7}\n ").unwrap();
+        assert_eq!(game.get_game_state().players[0].name.as_deref(), Some("Ada"));
+
+        assert_eq!(game.set_player_name(1, " \u{7} "), Err(PlayerNameError::Empty));
+        assert!(matches!(game.set_player_name(1, &"x".repeat(MAX_PLAYER_NAME_LEN + 1)), Err(PlayerNameError::TooLong { .. })));
+    }
+
+    #[test]
+    fn interpolation_spans_the_last_tick() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, -9.81], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.6, "x2": 0.55, "y2": 0.7 }
+        ] }"#)));
+        for _ in 0..10 {
+            game.tick();
+        }
+        let before = game.get_game_state().objects[0].y;
+        game.tick();
+        let after = game.get_game_state().objects[0].y;
+        assert!(after < before);
+
+        assert!((game.get_interpolated_state(0.0).objects[0].y - before).abs() < 1e-5);
+        assert!((game.get_interpolated_state(1.0).objects[0].y - after).abs() < 1e-5);
+        let halfway = game.get_interpolated_state(0.5).objects[0].y;
+        assert!((halfway - (before + after) / 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn a_frame_of_substeps_matches_a_tick() {
+        let falling = || Game::builder().map(map(r#"{ "gravity": [0.0, -9.81], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.6, "x2": 0.55, "y2": 0.7 }
+        ] }"#)).substeps(10).build().unwrap();
+        let (mut ticked, mut stepped) = (falling(), falling());
+        ticked.tick();
+        for remaining in (1..=10).rev() {
+            assert_eq!(stepped.substeps_remaining(), remaining);
+            stepped.substep();
+        }
+        assert_eq!(stepped.tick, ticked.tick);
+
+        let y = |game: &Game| first_collider(game, object_handles(game)[0]).position().translation.y;
+        assert!(y(&ticked) < y(&falling()));
+        assert!((y(&ticked) - y(&stepped)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn objects_fall_through_disabled_groups() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, -9.81], "entities": [
+            { "shape": "rect", "x1": 0.3, "y1": 0.48, "x2": 0.7, "y2": 0.5, "is_static": true, "group": "gate" },
+            { "shape": "rect", "x1": 0.47, "y1": 0.55, "x2": 0.53, "y2": 0.6 }
+        ] }"#)));
+        let square = object_handles(&game)[1];
+        let y = |game: &Game| first_collider(game, square).position().translation.y;
+        for _ in 0..60 {
+            game.tick();
+        }
+        assert!(y(&game) > 0.0);
+
+        assert!(game.set_group_enabled("gate", false));
+        assert!(!game.set_group_enabled("missing", false));
+        for _ in 0..120 {
+            game.tick();
+        }
+        assert!(y(&game) < -3.0);
+        assert!(!game.get_game_state().objects.iter().find(|object| object.id == object_handles(&game)[0].into_raw_parts().0).unwrap().enabled);
+    }
+
+    #[test]
+    fn extreme_impulses_raise_the_instability_flag() {
+        let mut game = still_box_game();
+        assert!(!game.get_game_state().unstable);
+        let square = object_handles(&game)[0];
+        let body = &mut game.rigid_body_set[square];
+        let impulse = vector![UNSTABLE_SPEED * 2.0 * body.mass(), 0.0];
+        body.apply_impulse(impulse, true);
+        game.tick();
+        assert!(game.physics_health().max_speed > UNSTABLE_SPEED);
+        assert!(game.physics_health().is_unstable());
+        assert!(game.get_game_state().unstable);
+    }
+
+    #[test]
+    fn held_tools_turn_to_follow_the_cursor() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.48, "x2": 0.55, "y2": 0.52, "is_tool": true }
+        ] }"#)));
+        let tool = object_handles(&game)[0];
+        game.add_player(1);
+        game.tick();
+        game.apply_input(1, grab_input());
+        game.tick();
+        assert!(game.get_game_state().objects[0].is_tool);
+
+        let mut drag = |dx: f32, dy: f32| {
+            for _ in 0..30 {
+                game.apply_input(1, input(dx, dy, true));
+                game.tick();
+            }
+            game.rigid_body_set[tool].rotation().angle()
+        };
+        assert!(drag(0.07, 0.0).abs() < 0.1);
+        assert!((drag(0.0, 0.1) - std::f32::consts::FRAC_PI_2).abs() < 0.1);
+    }
+
+    #[test]
+    fn normalized_state_matches_map_coordinates() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55 },
+            { "shape": "circle", "x": 0.2, "y": 0.7, "radius": 0.05 }
+        ] }"#)));
+        game.add_player(1);
+        let state = game.get_game_state_normalized();
+        let close = |value: f32, expected: f32| (value - expected).abs() < 1e-5;
+
+        let square = &state.objects[0];
+        assert!(close(square.x, 0.5) && close(square.y, 0.5));
+        assert!(close(square.half_width.unwrap(), 0.05) && close(square.half_height.unwrap(), 0.05));
+        let circle = &state.objects[1];
+        assert!(close(circle.x, 0.2) && close(circle.y, 0.7));
+        assert!(close(circle.radius.unwrap(), 0.05));
+        assert!(close(state.players[0].x, 0.5) && close(state.players[0].y, 0.5));
+    }
+
+    #[test]
+    fn spawn_requests_add_objects_within_the_cooldown() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "player_spawns": true }"#)));
+        game.add_player(1);
+        let start = game.object_count();
+        let spawn = |game: &mut Game| {
+            game.apply_input(1, PlayerInput { spawn_request: Some(SpawnRequest { shape: Some("circle".to_string()), size: Some(0.2) }), ..input(0.0, 0.0, false) });
+            game.tick().iter().filter(|event| matches!(event, GameEvent::Spawned { player_id: 1, .. })).count()
+        };
+
+        assert_eq!(spawn(&mut game), 1);
+        assert_eq!(game.object_count(), start + 1);
+        for _ in 1..SPAWN_COOLDOWN_TICKS {
+            spawn(&mut game);
+        }
+        assert_eq!(game.object_count(), start + 1);
+        assert_eq!(spawn(&mut game), 1);
+        assert_eq!(game.object_count(), start + 2);
+    }
+
+    #[test]
+    fn player_ids_come_back_sorted() {
+        let mut game = still_box_game();
+        for player_id in [42, 7, 19, 3] {
+            game.add_player(player_id);
+        }
+        assert_eq!(game.player_ids(), vec![3, 7, 19, 42]);
+        game.remove_player(19);
+        assert_eq!(game.player_ids(), vec![3, 7, 42]);
+    }
+
+    #[test]
+    fn region_queries_return_exactly_the_objects_inside() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.1, "y1": 0.45, "x2": 0.15, "y2": 0.55 },
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55 },
+            { "shape": "circle", "x": 0.85, "y": 0.5, "radius": 0.02 }
+        ] }"#)));
+        game.add_player(1);
+        game.tick();
+        let ids: Vec<u32> = object_handles(&game).iter().map(|handle| handle.into_raw_parts().0).collect();
+
+        assert_eq!(game.objects_in_aabb(Point2::new(-1.0, -1.0), Point2::new(1.0, 1.0)), vec![ids[1]]);
+        assert_eq!(game.objects_in_aabb(Point2::new(-7.0, -1.0), Point2::new(0.0, 1.0)), vec![ids[0], ids[1]]);
+        assert_eq!(game.objects_in_aabb(Point2::new(4.0, -1.0), Point2::new(8.0, 1.0)), vec![ids[2]]);
+        assert!(game.objects_in_aabb(Point2::new(-1.0, 2.0), Point2::new(1.0, 4.0)).is_empty());
+    }
+
+    #[test]
+    fn settled_objects_freeze_out_of_the_solver() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, -9.81], "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.02, "x2": 0.55, "y2": 0.12, "freeze_when_settled": true },
+            { "shape": "rect", "x1": 0.15, "y1": 0.02, "x2": 0.25, "y2": 0.12 }
+        ] }"#)));
+        let (freezing, plain) = (object_handles(&game)[0], object_handles(&game)[1]);
+        game.tick();
+        assert!(game.island_manager.active_dynamic_bodies().contains(&freezing));
+
+        for _ in 0..(FREEZE_AFTER_TICKS * 4) {
+            game.tick();
+        }
+        assert!(game.rigid_body_set[freezing].is_fixed());
+        assert!(!game.island_manager.active_dynamic_bodies().contains(&freezing));
+        assert!(game.rigid_body_set[plain].is_dynamic());
+        let objects = game.get_game_state().objects;
+        assert!(objects[0].is_frozen && !objects[1].is_frozen);
+    }
+
+    #[test]
+    fn removals_leave_no_stale_side_map_entries() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "name": "a", "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55, "color": "red", "is_tool": true, "group": "g" },
+            { "name": "b", "shape": "rect", "x1": 0.1, "y1": 0.1, "x2": 0.15, "y2": 0.15, "freeze_when_settled": true },
+            { "shape": "rect", "x1": 0.8, "y1": 0.1, "x2": 0.85, "y2": 0.15, "color": "blue", "z_layer": 2 },
+            { "shape": "circle", "x": 0.8, "y": 0.8, "radius": 0.02, "group": "g" }
+        ], "ropes": [{ "from": "a", "to": "b", "length": 5.0 }] }"#)));
+        game.add_player(1);
+        game.tick();
+        game.apply_input(1, grab_input());
+        game.tick();
+        assert!(game.is_grabbing(1));
+        let handles = object_handles(&game);
+
+        // Removed the game's own way, which cleans up as it goes.
+        game.remove_object(handles[2]);
+        game.remove_object(handles[3]);
+        assert_eq!(game.compact(), 0);
+
+        // Removed behind the game's back, straight from the body set.
+        for handle in &handles[..2] {
+            game.rigid_body_set.remove(*handle, &mut game.island_manager, &mut game.collider_set, &mut game.impulse_joint_set, &mut game.multibody_joint_set, true);
+        }
+        assert!(game.compact() > 0);
+        assert!(game.render_data.is_empty() && game.tools.is_empty() && game.settling.is_empty() && game.frozen.is_empty());
+        assert!(game.ropes.is_empty() && game.spawn_slots.is_empty() && game.last_touched_by.is_empty());
+        assert!(game.grab_joints.is_empty() && game.grab_charges.is_empty());
+        assert!(game.entity_groups.values().all(Vec::is_empty));
+        assert!(game.previous_positions.keys().all(|handle| game.rigid_body_set.contains(*handle)));
+        assert_eq!(game.compact(), 0);
+    }
+
+    #[test]
+    fn held_objects_never_touch_their_grabber() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "hand_tether": true, "entities": [
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55 }
+        ] }"#)));
+        game.add_player(1);
+        game.add_player(2);
+        game.tick();
+        game.apply_input(1, grab_input());
+        game.tick();
+        for _ in 0..30 {
+            game.apply_input(1, input(0.05, 0.02, true));
+            game.tick();
+        }
+        assert!(game.is_grabbing(1));
+
+        let object_collider = game.rigid_body_set[object_handles(&game)[0]].colliders()[0];
+        let hooks = GhostHooks { holders: game.grab_holders(), owners: game.body_owners(), ghosted: &game.ghosted, pvp: game.pvp };
+        let filter = |player_id: PlayerId| {
+            let player_collider = game.players[&player_id];
+            hooks.filter_contact_pair(&PairFilterContext {
+                bodies: &game.rigid_body_set,
+                colliders: &game.collider_set,
+                collider1: player_collider,
+                collider2: object_collider,
+                rigid_body1: game.collider_set[player_collider].parent(),
+                rigid_body2: game.collider_set[object_collider].parent(),
+            })
+        };
+        assert_eq!(filter(1), None);
+        assert_eq!(filter(2), Some(SolverFlags::COMPUTE_IMPULSES));
+        assert!(game.narrow_phase.contact_pair(game.players[&1], object_collider).is_none_or(|pair| !pair.has_any_active_contact));
+    }
+
+    #[test]
+    fn bars_grabbed_at_the_end_follow_by_that_end() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "grab_mode": "rigid", "entities": [
+            { "shape": "rect", "x1": 0.4, "y1": 0.49, "x2": 0.6, "y2": 0.51 }
+        ] }"#)));
+        let bar = object_handles(&game)[0];
+        game.add_player_at(1, Point2::new(1.5, 0.0));
+        game.tick();
+        game.apply_input(1, grab_input());
+        game.tick();
+        assert!(game.is_grabbing(1));
+
+        let grabbed_end = game.rigid_body_set[bar].position().inverse() * Point2::new(1.5, 0.0);
+        for _ in 0..20 {
+            game.apply_input(1, input(0.0, 0.1, true));
+            game.tick();
+        }
+        for _ in 0..10 {
+            game.apply_input(1, input(0.0, 0.0, true));
+            game.tick();
+        }
+        let player = &game.get_players_state()[0];
+        let cursor = Point2::new(player.x, player.y);
+        let bar_body = &game.rigid_body_set[bar];
+        assert!((bar_body.position() * grabbed_end - cursor).norm() < 0.05);
+        assert!(bar_body.rotation().angle().abs() > 0.1);
+    }
+
+    #[test]
+    fn autostep_climbs_a_low_step() {
+        let final_x = |autostep: bool| {
+            let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0] }"#)));
+            let floor = Boundary { x: 0.0, y: -4.5, half_width: 8.0, half_height: 0.1, phase: false, lethal: false };
+            let step = Boundary { x: 1.0, y: -4.375, half_width: 0.2, half_height: 0.025, phase: false, lethal: false };
+            game.rebuild_boundaries(vec![floor, step]);
+            if autostep {
+                game.set_player_controller(PlayerControllerData { autostep_height: Some(0.1), autostep_min_width: Some(0.01), ..Default::default() });
+            }
+            game.add_player_at(1, Point2::new(0.0, -4.4 + 0.005));
+            game.tick();
+            for _ in 0..40 {
+                game.apply_input(1, input(0.05, -0.01, false));
+                game.tick();
+            }
+            game.get_players_state()[0].x
+        };
+        assert!(final_x(false) < 0.8);
+        assert!(final_x(true) > 1.0);
+    }
+
+    #[test]
+    fn hand_offs_keep_the_object_grabbed() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        game.add_player(2);
+        let object_id = object_handles(&game)[0].into_raw_parts().0;
+        game.apply_input(1, grab_input());
+        game.tick();
+
+        assert!(game.transfer_grab(1, 2, object_id));
+        assert!(game.events().contains(&GameEvent::Transferred { from: 1, to: 2, object_id }));
+        assert!(!game.transfer_grab(1, 2, object_id));
+        for _ in 0..10 {
+            let object = &game.get_game_state().objects[0];
+            assert!(object.is_grabbed);
+            assert_eq!(object.grabbed_by, Some(2));
+            game.apply_input(1, input(0.0, 0.0, false));
+            game.apply_input(2, input(0.05, 0.0, true));
+            game.tick();
+        }
+        assert!(!game.is_grabbing(1) && game.is_grabbing(2));
+    }
+
+    #[test]
+    fn zero_area_rects_are_skipped() {
+        let game = Game::new(Some(map(r#"{ "entities": [
+            { "shape": "rect", "x1": 0.2, "y1": 0.4, "x2": 0.2, "y2": 0.5 },
+            { "shape": "rect", "x1": 0.4, "y1": 0.4, "x2": 0.5, "y2": 0.5 },
+            { "shape": "circle", "x": 0.7, "y": 0.5, "radius": 0.0 },
+            { "shape": "rect", "x1": -1.0, "y1": -1.0, "x2": 2.0, "y2": 0.1 }
+        ] }"#)));
+        assert_eq!(game.object_count(), 2);
+        let objects = game.get_game_state().objects;
+        assert!((objects[0].half_width.unwrap() - 0.8).abs() < 1e-4);
+        assert!(objects[1].half_width.unwrap() <= 8.0 && objects[1].half_height.unwrap() <= 4.5);
+    }
+
+    #[test]
+    fn only_death_objects_are_hazards() {
+        let game = Game::new(Some(map(r#"{ "death_walls": ["floor"], "entities": [
+            { "shape": "rect", "x1": 0.1, "y1": 0.1, "x2": 0.2, "y2": 0.2 },
+            { "shape": "rect", "x1": 0.4, "y1": 0.0, "x2": 0.6, "y2": 0.05, "is_static": true, "is_death": true },
+            { "shape": "circle", "x": 0.7, "y": 0.5, "radius": 0.02, "is_static": true }
+        ] }"#)));
+        let hazards = game.hazards();
+        assert_eq!(hazards.len(), 1);
+        assert_eq!(hazards[0].id, object_handles(&game)[1].into_raw_parts().0);
+        assert_eq!(hazards[0].user_data, DEATH_USER_DATA);
+        assert_eq!(game.boundaries().iter().filter(|boundary| boundary.lethal).count(), 1);
+    }
+
+    #[test]
+    fn sampled_flicks_hit_walls_a_single_jump_misses() {
+        // Cursors only collide with walls, so the obstacle is a short free-standing wall above the
+        // straight line between the flick's start and end.
+        let end_x = |mouse_path: Option<Vec<(f32, f32)>>| {
+            let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0] }"#)));
+            game.rebuild_boundaries(vec![Boundary { x: 1.0, y: 1.0, half_width: 0.1, half_height: 0.5, phase: false, lethal: false }]);
+            game.add_player(1);
+            game.tick();
+            game.apply_input(1, PlayerInput { mouse_path, ..input(2.0, 0.0, false) });
+            game.tick();
+            game.get_players_state()[0].x
+        };
+        assert!((end_x(None) - 2.0).abs() < 1e-3);
+        assert!(end_x(Some(vec![(0.0, 1.0), (2.0, 0.0), (0.0, -1.0)])) < 0.9);
+    }
+
+    #[test]
+    fn squares_in_a_corner_touch_both_walls() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [-3.0, -9.81], "entities": [
+            { "shape": "rect", "x1": 0.02, "y1": 0.03, "x2": 0.07, "y2": 0.1 },
+            { "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.5, "y2": 0.5, "gravity_scale": 0.0 }
+        ] }"#)));
+        for _ in 0..120 {
+            game.tick();
+        }
+        assert_eq!(game.get_game_state().objects[0].contact_count, None);
+
+        game.include_aabbs = true;
+        let objects = game.get_game_state().objects;
+        assert!(objects[0].contact_count.unwrap() >= 2);
+        assert_eq!(objects[1].contact_count, Some(0));
+    }
+
+    #[test]
+    fn object_order_survives_unrelated_spawns_and_removals() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.1, "y1": 0.4, "x2": 0.15, "y2": 0.5, "z_layer": 1 },
+            { "shape": "rect", "x1": 0.3, "y1": 0.4, "x2": 0.35, "y2": 0.5 },
+            { "shape": "rect", "x1": 0.5, "y1": 0.4, "x2": 0.55, "y2": 0.5, "z_layer": -1 },
+            { "shape": "circle", "x": 0.7, "y": 0.5, "radius": 0.02 }
+        ] }"#)));
+        let handles = object_handles(&game);
+        let ids = |game: &Game| -> Vec<u32> { game.get_game_state().objects.iter().map(|object| object.id).collect() };
+        let id = |index: usize| handles[index].into_raw_parts().0;
+        assert_eq!(ids(&game), vec![id(2), id(1), id(3), id(0)]);
+
+        game.remove_object(handles[3]);
+        let spawned = game.spawn_object("rect", 0.2, Point2::new(0.0, 3.0)).unwrap();
+        game.tick();
+        assert_eq!(ids(&game), vec![id(2), id(1), spawned.into_raw_parts().0, id(0)]);
+        game.remove_object(spawned);
+        game.tick();
+        assert_eq!(ids(&game), vec![id(2), id(1), id(0)]);
+    }
+
+    #[test]
+    fn thrown_objects_report_the_wall_they_hit() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        let object_id = object_handles(&game)[0].into_raw_parts().0;
+        game.apply_input(1, grab_input());
+        game.tick();
+        for _ in 0..5 {
+            game.apply_input(1, input(0.3, 0.0, true));
+            game.tick();
+        }
+        game.apply_input(1, input(0.0, 0.0, false));
+        let mut impacts = Vec::new();
+        for _ in 0..120 {
+            impacts.extend(game.tick().iter().filter_map(|event| match event {
+                GameEvent::WallImpact { object_id: id, wall_index, magnitude } if *id == object_id => Some((*wall_index, *magnitude)),
+                _ => None,
+            }));
+        }
+
+        let (wall_index, magnitude) = impacts[0];
+        let wall = &game.boundaries()[wall_index];
+        assert!(game.boundaries().iter().all(|other| other.x <= wall.x));
+        assert!(magnitude >= WALL_IMPACT_MIN_SPEED);
+    }
+
+    #[test]
+    fn density_changes_impulse_response_but_not_fall_speed() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, -9.81], "default_density": 3.0, "entities": [
+            { "shape": "rect", "x1": 0.2, "y1": 0.6, "x2": 0.25, "y2": 0.65, "density": 1.0 },
+            { "shape": "rect", "x1": 0.6, "y1": 0.6, "x2": 0.65, "y2": 0.65 }
+        ] }"#)));
+        let (light, heavy) = (object_handles(&game)[0], object_handles(&game)[1]);
+        for handle in [light, heavy] {
+            game.rigid_body_set[handle].apply_impulse(vector![1.0, 0.0], true);
+        }
+        for _ in 0..10 {
+            game.tick();
+        }
+        let (light, heavy) = (game.rigid_body_set[light].linvel(), game.rigid_body_set[heavy].linvel());
+        assert!((light.x - heavy.x * 3.0).abs() < 1e-3);
+        assert!(light.y < 0.0 && (light.y - heavy.y).abs() < 1e-3);
+    }
+
+    #[test]
+    fn stunned_players_ignore_input_until_the_stun_ends() {
+        let mut game = still_box_game();
+        game.add_player(1);
+        game.stun_player(1, 10, false);
+        assert_eq!(game.get_players_state()[0].stunned_ticks, 10);
+        for _ in 0..10 {
+            game.apply_input(1, input(0.1, 0.0, false));
+            game.tick();
+            assert_eq!(game.get_players_state()[0].x, 0.0);
+        }
+        assert_eq!(game.get_players_state()[0].stunned_ticks, 0);
+
+        game.apply_input(1, input(0.1, 0.0, false));
+        game.tick();
+        assert!((game.get_players_state()[0].x - 0.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn heavier_objects_strain_the_grab_more() {
+        let peak_strain = |density: f32| {
+            let mut game = Game::new(Some(map(&format!(r#"{{ "gravity": [0.0, 0.0], "entities": [
+                {{ "shape": "rect", "x1": 0.45, "y1": 0.45, "x2": 0.55, "y2": 0.55, "density": {} }}
+            ] }}"#, density))));
+            game.add_player(1);
+            game.tick();
+            game.apply_input(1, grab_input());
+            game.tick();
+            assert_eq!(game.get_players_state()[0].grab_strain, game.grab_strain(1));
+            (0..10).map(|_| {
+                game.apply_input(1, input(0.1, 0.0, true));
+                game.tick();
+                game.get_players_state()[0].grab_strain
+            }).fold(0.0, f32::max)
+        };
+        let light = peak_strain(1.0);
+        assert!(light > 0.0);
+        assert!(peak_strain(10.0) > light * 2.0);
+    }
+
+    #[cfg(feature = "deterministic")]
+    #[test]
+    fn identical_inputs_give_identical_state_hashes() {
+        let build = || {
+            let mut game = Game::builder().deterministic(true).build().unwrap();
+            game.add_player(1);
+            game.add_player(2);
+            game
+        };
+        let (mut a, mut b) = (build(), build());
+        let start = a.state_hash();
+        for step in 0..600u32 {
+            for game in [&mut a, &mut b] {
+                let phase = step as f32 * 0.05;
+                game.apply_input(1, PlayerInput { grab_pressed: step % 120 == 0, ..input(phase.cos() * 0.01, phase.sin() * 0.01, step % 120 < 90) });
+                game.apply_input(2, input(-0.004, 0.002, step % 200 < 150));
+                game.tick();
+            }
+            assert_eq!(a.state_hash(), b.state_hash(), "diverged on step {}", step);
+        }
+        assert_ne!(a.state_hash(), start);
+    }
+
+    #[cfg(feature = "deterministic")]
+    #[test]
+    fn identical_inputs_give_byte_identical_snapshots() {
+        let build = || {
+            let mut game = Game::builder().deterministic(true).build().unwrap();
+            game.add_player(1);
+            game.add_player(2);
+            game
+        };
+        let (mut a, mut b) = (build(), build());
+        assert_eq!(a.snapshot().unwrap(), b.snapshot().unwrap());
+        for step in 0..300u32 {
+            for game in [&mut a, &mut b] {
+                let phase = step as f32 * 0.05;
+                game.apply_input(1, PlayerInput { grab_pressed: step % 100 == 0, ..input(phase.cos() * 0.01, phase.sin() * 0.01, step % 100 < 70) });
+                game.apply_input(2, input(-0.004, 0.002, step % 150 < 100));
+                game.tick();
+            }
+            if step % 10 == 9 {
+                assert_eq!(a.snapshot().unwrap(), b.snapshot().unwrap(), "diverged on step {}", step);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "deterministic"))]
+    #[test]
+    fn deterministic_snapshots_need_the_feature_to_restore() {
+        let mut game = Game::new(None);
+        assert!(Game::restore(&game.snapshot().unwrap()).is_ok());
+        game.deterministic = true;
+        assert!(matches!(Game::restore(&game.snapshot().unwrap()), Err(SnapshotError::DeterminismUnavailable)));
+    }
+
+    #[test]
+    fn broken_timeline_spawns_are_rejected() {
+        let check = |event: &str| map(&format!(r#"{{ "timeline": [{{ "tick": 1, "type": "set_gravity", "gravity": [0, 1] }}, {}] }}"#, event)).validate();
+        assert_eq!(check(r#"{ "tick": 5, "type": "spawn", "shape": "rect", "size": 0.2, "position": [0.5, 0.5] }"#), Ok(()));
+        assert_eq!(check(r#"{ "tick": 5, "type": "spawn", "shape": "star", "size": 0.2, "position": [0.5, 0.5] }"#), Err(GameInitError::InvalidTimedSpawn { index: 1, field: "shape" }));
+        assert_eq!(check(r#"{ "tick": 5, "type": "spawn", "shape": "circle", "size": 0.0, "position": [0.5, 0.5] }"#), Err(GameInitError::InvalidTimedSpawn { index: 1, field: "size" }));
+        assert_eq!(check(r#"{ "tick": 5, "type": "spawn", "shape": "rect", "size": 1e39, "position": [0.5, 0.5] }"#), Err(GameInitError::InvalidTimedSpawn { index: 1, field: "size" }));
+        assert_eq!(check(r#"{ "tick": 5, "type": "spawn", "shape": "rect", "size": 0.2, "position": [1e39, 0.5] }"#), Err(GameInitError::InvalidTimedSpawn { index: 1, field: "position" }));
+
+        let mut game = Game::new(None);
+        let count = game.object_count();
+        for size in [0.0, -0.1, f32::NAN, f32::INFINITY] {
+            assert!(game.spawn_object("rect", size, Point2::origin()).is_none());
+        }
+        assert_eq!(game.object_count(), count);
+    }
+
+    #[test]
+    fn timeline_spawns_land_exactly_on_their_tick() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, 0.0], "timeline": [
+            { "tick": 30, "type": "spawn", "shape": "rect", "size": 0.2, "position": [0.5, 0.5] }
+        ] }"#)));
+        let start = game.object_count();
+        while game.tick < 30 {
+            game.tick();
+            assert_eq!(game.object_count(), start);
+        }
+        game.tick();
+        assert_eq!(game.object_count(), start + 1);
+        for _ in 0..30 {
+            game.tick();
+        }
+        assert_eq!(game.object_count(), start + 1);
+    }
+
+    #[test]
+    fn weightless_objects_hang_while_others_fall() {
+        let mut game = Game::new(Some(map(r#"{ "gravity": [0.0, -9.81], "entities": [
+            { "shape": "rect", "x1": 0.2, "y1": 0.6, "x2": 0.25, "y2": 0.65, "gravity_scale": 0.0 },
+            { "shape": "rect", "x1": 0.6, "y1": 0.6, "x2": 0.65, "y2": 0.65 }
+        ] }"#)));
+        let (weightless, normal) = (object_handles(&game)[0], object_handles(&game)[1]);
+        let y = |game: &Game, handle| first_collider(game, handle).position().translation.y;
+        let (weightless_start, normal_start) = (y(&game, weightless), y(&game, normal));
+        for _ in 0..60 {
+            game.tick();
+        }
+        assert!((y(&game, weightless) - weightless_start).abs() < 1e-4);
+        assert!(y(&game, normal) < normal_start - 1.0);
+    }
+}
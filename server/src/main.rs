@@ -1,161 +1,1371 @@
 use dotenv::dotenv;
-use futures_util::{SinkExt, StreamExt};
-use log::{info, warn};
+use futures_util::{Sink, SinkExt, StreamExt};
+use log::{error, info, warn};
 use native_tls::{Identity, TlsAcceptor};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env,
     fs::File,
     io::Read,
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    pin::Pin,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
+    io::{AsyncRead, AsyncWrite},
     net::{TcpListener, TcpStream},
-    sync::Mutex,
-    time::interval,
+    sync::{Mutex, Notify},
+    time::{interval, MissedTickBehavior},
 };
-use tokio_native_tls::TlsStream;
 use tokio_tungstenite::{
-    accept_async,
-    tungstenite::protocol::Message,
-    WebSocketStream,
+    accept_hdr_async,
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        protocol::Message,
+        Error as WsError,
+    },
 };
-use game_logic::{Game, PlayerInput, PlayerId, GameState};
-use serde::Serialize;
+use game_logic::{CameraHint, Game, PlayerInput, PlayerId, GameState, Player, SnapshotError};
+use serde::{Deserialize, Serialize};
 use tokio_native_tls::TlsAcceptor as TokioTlsAcceptor;
 
 
-type PeerMap = Arc<Mutex<HashMap<SocketAddr, futures_util::stream::SplitSink<WebSocketStream<TlsStream<TcpStream>>, Message>>>>;
+// Boxed so TLS and plain TCP connections can share one peer map.
+type PeerSink = Pin<Box<dyn Sink<Message, Error = WsError> + Send>>;
+type PeerMap = Arc<Mutex<HashMap<SocketAddr, PeerSink>>>;
 type InputQueue = Arc<Mutex<Vec<(PlayerId, PlayerInput)>>>;
+type RoomMap = Arc<Mutex<HashMap<String, Arc<Room>>>>;
+// Addresses banned by an admin, refused at accept until the server restarts.
+type BanList = Arc<Mutex<HashSet<IpAddr>>>;
+// Where rooms are saved, when `ROOM_STATE_DIR` is set.
+type StateDir = Option<Arc<PathBuf>>;
+
+// Each room runs its own game loop and only ever touches its own peers.
+struct Room {
+    name: String,
+    peers: PeerMap,
+    // Each player's connection and the signal that makes its task hang up, for admin kicks.
+    connections: Mutex<HashMap<PlayerId, (SocketAddr, Arc<Notify>)>>,
+    game: Arc<Mutex<Game>>,
+    input_queue: InputQueue,
+    input_notify: Notify,
+    // Ticks per timed round, or `None` for an endless room.
+    round_length_ticks: Option<u64>,
+    // Peers that get a full snapshot on the next tick even if it's a players-only tick.
+    keyframe_requests: Mutex<HashSet<SocketAddr>>,
+    // How much of the full state each peer asked for; peers not listed get `StateDetail::Standard`.
+    subscriptions: Mutex<HashMap<SocketAddr, StateDetail>>,
+    // State goes out on every Nth tick; inputs are still applied every tick.
+    broadcast_every: u64,
+    // Set once the last player leaves; the game loop stops and the room is dropped from the map.
+    closed: AtomicBool,
+}
+
+const DEFAULT_ROOM: &str = "lobby";
+const MAX_ROOM_NAME_LEN: usize = 32;
+const MAX_ROUND_SECONDS: u64 = 60 * 60;
+
+const TICKS_PER_SECOND: u64 = 60;
+const TICK_PERIOD: Duration = Duration::from_millis(1000 / TICKS_PER_SECOND);
+
+const ROOM_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+// How long a room restored at startup waits for someone to join before it closes again.
+const RESTORED_ROOM_GRACE: Duration = Duration::from_secs(5 * 60);
+
+const STATE_HASH_INTERVAL_TICKS: u64 = 60;
+// How often each room keeps a snapshot in memory to fall back on if a tick panics.
+const RECOVERY_SNAPSHOT_INTERVAL_TICKS: u64 = 60;
+// Every third broadcast carries the full object state (20Hz at the default rate); the others carry
+// only player cursors.
+const FULL_SNAPSHOT_INTERVAL_BROADCASTS: u64 = 3;
+// Overridden with `BROADCAST_EVERY`, e.g. `2` to simulate at 60Hz but send at 30Hz.
+const DEFAULT_BROADCAST_EVERY: u64 = 1;
+// Heartbeat rate for rooms whose world has fully settled; any input restores the full rate.
+const IDLE_TICK_MILLIS: u64 = 1000 / 5;
 
 #[derive(Serialize)]
 #[serde(tag = "type")]
 enum ServerMessage<'a> {
     Welcome { id: PlayerId },
+    WorldInfo { width: f32, height: f32, camera: CameraHint },
+    Roster { ids: Vec<PlayerId> },
+    PlayerJoined { id: PlayerId },
+    PlayerLeft { id: PlayerId },
+    StateHash { tick: u64, hash: String },
+    RoundStart { tick: u64, length_ticks: u64 },
+    GravityChanged { x: f32, y: f32 },
+    RoundEnd { scores: HashMap<PlayerId, i32> },
+    Error { code: ErrorCode, message: String },
+    PlayersUpdate { players: Vec<Player> },
+    // The full state for peers subscribed to positions only.
+    ObjectPositions { players: Vec<Player>, objects: Vec<ObjectPosition> },
     GameState(&'a GameState),
 }
 
+#[derive(Serialize)]
+struct ObjectPosition {
+    id: u32,
+    x: f32,
+    y: f32,
+    rotation: f32,
+}
+
+// Picked from the fields a peer subscribes to: `["position"]` gets just where objects are,
+// anything with `"debug"` adds AABBs and contact counts, and `[]` goes back to the usual state.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum StateDetail {
+    Positions,
+    #[default]
+    Standard,
+    Debug,
+}
+
+const SUBSCRIBABLE_FIELDS: [&str; 2] = ["position", "debug"];
+
+impl StateDetail {
+    fn from_fields(fields: &[String]) -> Result<Self, String> {
+        if let Some(unknown) = fields.iter().find(|field| !SUBSCRIBABLE_FIELDS.contains(&field.as_str())) {
+            return Err(format!("unknown state field {:?}, expected one of {:?}", unknown, SUBSCRIBABLE_FIELDS));
+        }
+        Ok(if fields.iter().any(|field| field == "debug") {
+            StateDetail::Debug
+        } else if !fields.is_empty() {
+            StateDetail::Positions
+        } else {
+            StateDetail::Standard
+        })
+    }
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ErrorCode {
+    // A text frame that didn't parse as any `ClientMessage`.
+    InvalidInput,
+    // Binary frames and anything else the server doesn't speak.
+    UnsupportedMessage,
+    // An admin command without the right token, sent while ADMIN_TOKEN is unset, or sent too soon
+    // after too many wrong tokens.
+    NotAuthorized,
+}
+
+// Every message names itself with a `type` tag, so one that doesn't parse is reported against the
+// variant and field that failed rather than as matching none of them.
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum ClientMessage {
+    // Per-frame player input, e.g. `{"type": "input", "mouse_dx": 0.1, "mouse_dy": 0, "is_mouse_down": false}`.
+    #[serde(rename = "input")]
+    Input(PlayerInput),
+    // Asks for a full snapshot after missing updates, e.g. `{"type": "resync"}`.
+    #[serde(rename = "resync")]
+    Resync,
+    // Sets the sender's display name, e.g. `{"type": "join", "name": "vova"}`.
+    #[serde(rename = "join")]
+    Join { name: String },
+    // Chooses what full state updates carry, e.g. `{"type": "subscribe", "fields": ["position"]}`.
+    #[serde(rename = "subscribe")]
+    Subscribe { fields: Vec<String> },
+    // Sandbox controls, e.g. `{"type": "SetGravity", "token": "...", "x": 0, "y": 2}`. They only work
+    // when the server has ADMIN_TOKEN set and the message carries the same token.
+    SetGravity { token: String, x: f32, y: f32 },
+    // Disconnects a player in the sender's room, e.g. `{"type": "Kick", "token": "...", "target": 3}`.
+    Kick { token: String, target: PlayerId },
+    // Like `Kick`, and refuses further connections from the player's IP address.
+    Ban { token: String, target: PlayerId },
+}
+
+impl ClientMessage {
+    // The token an admin command was sent with, or `None` for messages anyone may send.
+    fn admin_token(&self) -> Option<&str> {
+        match self {
+            ClientMessage::SetGravity { token, .. } | ClientMessage::Kick { token, .. } | ClientMessage::Ban { token, .. } => Some(token),
+            _ => None,
+        }
+    }
+}
+
+// Errors are echoed back at most this often per connection so a spamming client can't turn the
+// server into an amplifier against itself; anything over the limit is only logged.
+const MAX_ERRORS_PER_WINDOW: u32 = 5;
+const ERROR_WINDOW: Duration = Duration::from_secs(1);
+
+struct ErrorLimiter {
+    window_start: Instant,
+    sent: u32,
+}
+
+impl ErrorLimiter {
+    fn new() -> Self {
+        Self { window_start: Instant::now(), sent: 0 }
+    }
+
+    fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        if now.duration_since(self.window_start) >= ERROR_WINDOW {
+            self.window_start = now;
+            self.sent = 0;
+        }
+        if self.sent < MAX_ERRORS_PER_WINDOW {
+            self.sent += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Wrong admin tokens a connection may send before its admin commands are refused unchecked for a
+// while, so a client can't guess the token at message rate.
+const MAX_FAILED_ADMIN_ATTEMPTS: u32 = 3;
+const ADMIN_LOCKOUT: Duration = Duration::from_secs(60);
+
+struct AdminAttempts {
+    failures: u32,
+    locked_until: Option<Instant>,
+}
+
+impl AdminAttempts {
+    fn new() -> Self {
+        Self { failures: 0, locked_until: None }
+    }
+
+    // Whether `token` matches the server's `admin_token`. Every miss counts toward a lockout, during
+    // which even the right token is refused.
+    fn check(&mut self, admin_token: Option<&str>, token: &str) -> bool {
+        if self.locked_until.is_some_and(|until| Instant::now() < until) {
+            return false;
+        }
+        let authorized = admin_token.is_some_and(|admin_token| !admin_token.is_empty() && tokens_match(admin_token, token));
+        if authorized {
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+            if self.failures >= MAX_FAILED_ADMIN_ATTEMPTS {
+                self.failures = 0;
+                self.locked_until = Some(Instant::now() + ADMIN_LOCKOUT);
+            }
+        }
+        authorized
+    }
+}
+
+// Takes the same time wherever two equal-length tokens differ, so a wrong guess doesn't reveal how
+// much of it was right.
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
     env_logger::init();
 
     let addr = "0.0.0.0:8088";
-    let cert_path = env::var("CERT_PATH").expect("CERT_PATH must be set");
-    let cert_pass = env::var("CERT_PASS").expect("CERT_PASS must be set");
 
-    let mut cert_file = File::open(&cert_path).expect("cannot open certificate");
-    let mut cert_buf = Vec::new();
-    cert_file.read_to_end(&mut cert_buf).expect("cannot read certificate");
-    let identity = Identity::from_pkcs12(&cert_buf, &cert_pass).expect("cannot create identity");
-    let tls_acceptor = Arc::new(TokioTlsAcceptor::from(
-        TlsAcceptor::builder(identity).build().expect("cannot create acceptor"),
-    ));
+    // DISABLE_TLS=1 serves plain ws:// for local development or behind a TLS-terminating proxy.
+    let disable_tls = env::var("DISABLE_TLS").is_ok_and(|value| value == "1" || value == "true");
+    let tls_acceptor = if disable_tls {
+        None
+    } else {
+        let cert_path = env::var("CERT_PATH").expect("CERT_PATH must be set");
+        let cert_pass = env::var("CERT_PASS").expect("CERT_PASS must be set");
+
+        let mut cert_file = File::open(&cert_path).expect("cannot open certificate");
+        let mut cert_buf = Vec::new();
+        cert_file.read_to_end(&mut cert_buf).expect("cannot read certificate");
+        let identity = Identity::from_pkcs12(&cert_buf, &cert_pass).expect("cannot create identity");
+        Some(Arc::new(TokioTlsAcceptor::from(
+            TlsAcceptor::builder(identity).build().expect("cannot create acceptor"),
+        )))
+    };
 
     let listener = TcpListener::bind(&addr).await.expect("Failed to bind");
-    info!("Listening on: wss://{}", addr);
+    let scheme = if disable_tls { "ws" } else { "wss" };
+    info!("Listening on: {}://{}", scheme, addr);
 
-    let peer_map = PeerMap::new(Mutex::new(HashMap::new()));
-    let game = Arc::new(Mutex::new(Game::new(None)));
-    let player_id_counter = Arc::new(AtomicU32::new(1));
-    let input_queue = InputQueue::new(Mutex::new(Vec::new()));
+    let rooms = RoomMap::new(Mutex::new(HashMap::new()));
+
+    // ROOM_STATE_DIR keeps rooms alive across restarts: they are reloaded here and saved periodically.
+    let state_dir: StateDir = env::var("ROOM_STATE_DIR").ok().map(|dir| Arc::new(PathBuf::from(dir)));
+    if let Some(dir) = &state_dir {
+        load_rooms(&rooms, dir).await;
+        tokio::spawn(save_rooms(rooms.clone(), dir.to_path_buf()));
+    }
 
-    // Spawn the game loop
-    tokio::spawn(game_loop(peer_map.clone(), game.clone(), input_queue.clone()));
+    let player_id_counter = Arc::new(AtomicU32::new(1));
+    let bans = BanList::new(Mutex::new(HashSet::new()));
 
     while let Ok((stream, addr)) = listener.accept().await {
+        if bans.lock().await.contains(&addr.ip()) {
+            info!("Refused connection from banned address {}", addr);
+            continue;
+        }
         let player_id = player_id_counter.fetch_add(1, Ordering::SeqCst);
         let acceptor = tls_acceptor.clone();
-        tokio::spawn(handle_connection(acceptor, peer_map.clone(), game.clone(), input_queue.clone(), stream, addr, player_id));
+        tokio::spawn(handle_connection(acceptor, rooms.clone(), bans.clone(), state_dir.clone(), stream, addr, player_id));
+    }
+}
+
+// Clients pick a room with the WebSocket URL path, e.g. `wss://host:8088/my-room`. Whoever creates
+// the room can make it timed with `?round_seconds=120`; the query is ignored for existing rooms.
+fn room_name_from_path(path: &str) -> String {
+    let name: String = path
+        .trim_matches('/')
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .take(MAX_ROOM_NAME_LEN)
+        .collect();
+    if name.is_empty() {
+        DEFAULT_ROOM.to_string()
+    } else {
+        name
+    }
+}
+
+// `?practice=1` asks for a private single-player room instead of the one named by the path.
+fn practice_from_query(query: Option<&str>) -> bool {
+    query.is_some_and(|query| query.split('&').any(|pair| matches!(pair, "practice" | "practice=1" | "practice=true")))
+}
+
+fn round_seconds_from_query(query: Option<&str>) -> Option<u64> {
+    query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("round_seconds="))
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|seconds| *seconds > 0)
+        .map(|seconds| seconds.min(MAX_ROUND_SECONDS))
+}
+
+// A room that was saved when it emptied picks up where it left off.
+async fn get_or_create_room(rooms: &RoomMap, name: &str, round_seconds: Option<u64>, state_dir: Option<&Path>) -> Option<Arc<Room>> {
+    let mut rooms = rooms.lock().await;
+    if let Some(room) = rooms.get(name) {
+        return Some(room.clone());
+    }
+
+    let saved_path = state_dir.map(|dir| dir.join(format!("{}.json", name))).filter(|path| path.exists());
+    let saved_game = match saved_path {
+        Some(path) => load_saved_game(name, &path).await,
+        None => None,
+    };
+    let game = match saved_game.map_or_else(|| Game::try_new(None), Ok) {
+        Ok(game) => game,
+        Err(e) => {
+            error!("Failed to create game for room {}: {}", name, e);
+            return None;
+        }
+    };
+    let room = spawn_room(name, game, round_seconds);
+    rooms.insert(name.to_string(), room.clone());
+    Some(room)
+}
+
+// Practice rooms are never added to the room map, so nobody else can join or see them, and they
+// aren't saved. The room closes like any other once its one player leaves.
+fn create_practice_room(player_id: PlayerId, round_seconds: Option<u64>) -> Option<Arc<Room>> {
+    let name = format!("practice-{}", player_id);
+    match Game::try_new(None) {
+        Ok(game) => Some(spawn_room(&name, game, round_seconds)),
+        Err(e) => {
+            error!("Failed to create game for room {}: {}", name, e);
+            None
+        }
+    }
+}
+
+// Called after a player leaves. The game lock is held across the check so a player joining at the
+// same moment either lands before the check (and keeps the room open) or sees `closed` and bails.
+// With a state directory the room is saved before it goes, still under the room map lock, so
+// whoever reopens it loads the saved state rather than racing the write.
+async fn close_room_if_empty(rooms: &RoomMap, room: &Arc<Room>, state_dir: Option<&Path>) {
+    let mut rooms = rooms.lock().await;
+    let snapshot = {
+        let game = room.game.lock().await;
+        if !game.is_empty() {
+            return;
+        }
+        room.closed.store(true, Ordering::SeqCst);
+        state_dir.map(|_| game.snapshot())
+    };
+    room.input_notify.notify_one();
+    // Practice rooms were never in the map, so they are neither removed nor saved.
+    if rooms.get(&room.name).is_some_and(|current| Arc::ptr_eq(current, room)) {
+        if let (Some(dir), Some(snapshot)) = (state_dir, snapshot) {
+            save_room(&room.name, snapshot, dir).await;
+        }
+        rooms.remove(&room.name);
+    }
+    info!("Closed empty room {}", room.name);
+}
+
+fn spawn_room(name: &str, game: Game, round_seconds: Option<u64>) -> Arc<Room> {
+    let room = Arc::new(Room {
+        name: name.to_string(),
+        peers: PeerMap::new(Mutex::new(HashMap::new())),
+        connections: Mutex::new(HashMap::new()),
+        game: Arc::new(Mutex::new(game)),
+        input_queue: InputQueue::new(Mutex::new(Vec::new())),
+        input_notify: Notify::new(),
+        round_length_ticks: round_seconds.map(|seconds| seconds * TICKS_PER_SECOND),
+        keyframe_requests: Mutex::new(HashSet::new()),
+        subscriptions: Mutex::new(HashMap::new()),
+        closed: AtomicBool::new(false),
+        broadcast_every: broadcast_every_from_env(),
+    });
+    info!("Created room {}", name);
+    tokio::spawn(game_loop(room.clone()));
+    room
+}
+
+fn broadcast_every_from_env() -> u64 {
+    env::var("BROADCAST_EVERY")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|every| *every > 0)
+        .unwrap_or(DEFAULT_BROADCAST_EVERY)
+}
+
+// Snapshots that can't be restored (e.g. written by an older build) are deleted rather than retried
+// on every start; the room simply begins fresh. Restored rooms start with nobody in them, so each
+// gets `RESTORED_ROOM_GRACE` to be rejoined before it is closed and saved like any emptied room.
+async fn load_rooms(rooms: &RoomMap, dir: &Path) {
+    let room_map = rooms.clone();
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Cannot read room state directory {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    let mut rooms = rooms.lock().await;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string) else {
+            continue;
+        };
+        if room_name_from_path(&name) != name {
+            continue;
+        }
+
+        if let Some(game) = load_saved_game(&name, &path).await {
+            let room = spawn_room(&name, game, None);
+            rooms.insert(name.clone(), room.clone());
+            tokio::spawn(close_restored_room_if_unused(room_map.clone(), room, dir.to_path_buf()));
+        }
+    }
+}
+
+async fn close_restored_room_if_unused(rooms: RoomMap, room: Arc<Room>, dir: PathBuf) {
+    tokio::time::sleep(RESTORED_ROOM_GRACE).await;
+    // Anyone who joined and left in the meantime already closed it.
+    if !room.closed.load(Ordering::SeqCst) {
+        close_room_if_empty(&rooms, &room, Some(dir.as_path())).await;
+    }
+}
+
+async fn load_saved_game(name: &str, path: &Path) -> Option<Game> {
+    let bytes = match tokio::fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read saved room {}: {}", name, e);
+            return None;
+        }
+    };
+    match Game::restore(&bytes) {
+        Ok(game) => {
+            info!("Restored room {} from {}", name, path.display());
+            Some(game)
+        }
+        Err(e) => {
+            warn!("Discarding saved room {}: {}", name, e);
+            if let Err(e) = tokio::fs::remove_file(path).await {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+            None
+        }
     }
 }
 
-async fn game_loop(peer_map: PeerMap, game: Arc<Mutex<Game>>, input_queue: InputQueue) {
-    let mut interval = interval(Duration::from_millis(1000 / 60)); // 60 FPS
+async fn save_rooms(rooms: RoomMap, dir: PathBuf) {
+    if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+        error!("Cannot create room state directory {}: {}", dir.display(), e);
+        return;
+    }
+
+    let mut save_interval = interval(ROOM_SAVE_INTERVAL);
+    save_interval.tick().await;
     loop {
-        interval.tick().await;
+        save_interval.tick().await;
 
-        let mut inputs = input_queue.lock().await;
-        let mut game = game.lock().await;
+        let rooms: Vec<Arc<Room>> = rooms.lock().await.values().cloned().collect();
+        for room in rooms {
+            let snapshot = room.game.lock().await.snapshot();
+            save_room(&room.name, snapshot, &dir).await;
+        }
+    }
+}
+
+async fn save_room(name: &str, snapshot: Result<Vec<u8>, SnapshotError>, dir: &Path) {
+    let bytes = match snapshot {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to snapshot room {}: {}", name, e);
+            return;
+        }
+    };
+
+    // Written to a temporary file first so a crash mid-write never leaves a truncated snapshot.
+    let path = dir.join(format!("{}.json", name));
+    let tmp_path = dir.join(format!("{}.json.tmp", name));
+    let result = match tokio::fs::write(&tmp_path, bytes).await {
+        Ok(()) => tokio::fs::rename(&tmp_path, &path).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        warn!("Failed to save room {}: {}", name, e);
+    }
+}
+
+async fn game_loop(room: Arc<Room>) {
+    // `Delay` rather than tokio's default `Burst`: after a slow tick the loop just resumes one period
+    // later instead of firing a run of back-to-back catch-up ticks, so a hiccup costs a little game
+    // time instead of making everything jump.
+    let mut idle_interval = interval(Duration::from_millis(IDLE_TICK_MILLIS));
+    idle_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut interval = interval(TICK_PERIOD);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut round_start_tick = room.game.lock().await.tick;
+    let mut last_good_snapshot: Option<Vec<u8>> = None;
+    loop {
+        // Rounds are timed in ticks, so timed rooms never drop to the idle rate.
+        let settled = room.round_length_ticks.is_none() && room.input_queue.lock().await.is_empty() && room.game.lock().await.is_settled();
+        if settled {
+            tokio::select! {
+                _ = idle_interval.tick() => {}
+                _ = room.input_notify.notified() => {
+                    // Back on the 60Hz clock rather than ticking now, so however many messages a
+                    // client sends, it can't make game time run faster.
+                    interval.reset();
+                    interval.tick().await;
+                }
+            }
+        } else {
+            interval.tick().await;
+        }
+        if room.closed.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let mut inputs = room.input_queue.lock().await;
+        let mut game = room.game.lock().await;
 
         for (player_id, input) in inputs.drain(..) {
             game.apply_input(player_id, input);
         }
 
-        game.tick();
+        let tick_started = Instant::now();
+        if tick_or_recover(&room.name, &mut game, last_good_snapshot.as_deref(), |game| {
+            game.tick();
+        }) {
+            round_start_tick = round_start_tick.min(game.tick);
+            let peers: Vec<SocketAddr> = room.peers.lock().await.keys().copied().collect();
+            room.keyframe_requests.lock().await.extend(peers);
+            continue;
+        }
+        let tick_time = tick_started.elapsed();
+        if tick_time > TICK_PERIOD {
+            warn!("Room {} tick {} took {:?}, over the {:?} budget", room.name, game.tick, tick_time, TICK_PERIOD);
+        }
+        if game.tick % RECOVERY_SNAPSHOT_INTERVAL_TICKS == 0 {
+            match game.snapshot() {
+                Ok(snapshot) => last_good_snapshot = Some(snapshot),
+                Err(e) => warn!("Failed to snapshot room {} for recovery: {}", room.name, e),
+            }
+        }
+
+        if let Some(length_ticks) = room.round_length_ticks {
+            if game.tick - round_start_tick >= length_ticks {
+                broadcast(&room.peers, &ServerMessage::RoundEnd { scores: game.scores().clone() }).await;
+                game.release_all_grabs();
+                game.restart();
+                game.reset_scores();
+                round_start_tick = game.tick;
+                broadcast(&room.peers, &ServerMessage::RoundStart { tick: round_start_tick, length_ticks }).await;
+            }
+        }
 
-        let game_state = game.get_game_state();
-        let game_state_msg = ServerMessage::GameState(&game_state);
-        let game_state_json = serde_json::to_string(&game_state_msg).unwrap();
+        // Clients interpolate across the skipped ticks.
+        if game.tick % room.broadcast_every != 0 {
+            continue;
+        }
 
-        let mut peers = peer_map.lock().await;
+        // Cursors move every frame but most objects don't, so between full snapshots only the
+        // players are sent and clients keep drawing the last objects they got.
+        let resync = std::mem::take(&mut *room.keyframe_requests.lock().await);
+        let subscriptions = room.subscriptions.lock().await.clone();
+        let is_full_tick = (game.tick / room.broadcast_every) % FULL_SNAPSHOT_INTERVAL_BROADCASTS == 0;
+        let players_json = if is_full_tick {
+            None
+        } else {
+            to_json(&ServerMessage::PlayersUpdate { players: game.get_players_state() })
+        };
+        // Each level of detail is serialized at most once, the first time a peer needs it.
+        let mut full_jsons: HashMap<StateDetail, Option<String>> = HashMap::new();
+
+        // The hash is sent as a hex string since JSON numbers can't carry a full u64 to JS.
+        let state_hash_json = if game.tick % STATE_HASH_INTERVAL_TICKS == 0 {
+            let state_hash_msg = ServerMessage::StateHash { tick: game.tick, hash: format!("{:016x}", game.state_hash()) };
+            to_json(&state_hash_msg)
+        } else {
+            None
+        };
+
+        // Every message is serialized once and sent as-is to each peer in this room that gets it.
+        let mut peers = room.peers.lock().await;
         for (addr, writer) in peers.iter_mut() {
-            if let Err(e) = writer.send(Message::Text(game_state_json.clone())).await {
-                warn!("Failed to send game state to {}: {}. Peer will be removed.", addr, e);
+            let json: &Option<String> = if is_full_tick || resync.contains(addr) {
+                let detail = subscriptions.get(addr).copied().unwrap_or_default();
+                full_jsons.entry(detail).or_insert_with(|| full_state_json(&mut game, detail))
+            } else {
+                &players_json
+            };
+            if let Some(json) = json {
+                if let Err(e) = writer.send(Message::Text(json.clone())).await {
+                    warn!("Failed to send game state to {} in room {}: {}. Peer will be removed.", addr, room.name, e);
+                }
+            }
+            if let Some(json) = &state_hash_json {
+                if let Err(e) = writer.send(Message::Text(json.clone())).await {
+                    warn!("Failed to send state hash to {}: {}", addr, e);
+                }
             }
         }
     }
 }
 
+fn full_state_json(game: &mut Game, detail: StateDetail) -> Option<String> {
+    match detail {
+        StateDetail::Positions => {
+            let mut objects = Vec::new();
+            game.for_each_object(|object| objects.push(ObjectPosition { id: object.id, x: object.x, y: object.y, rotation: object.rotation }));
+            to_json(&ServerMessage::ObjectPositions { players: game.get_players_state(), objects })
+        }
+        StateDetail::Standard => to_json(&ServerMessage::GameState(&game.get_game_state())),
+        StateDetail::Debug => {
+            let include_aabbs = std::mem::replace(&mut game.include_aabbs, true);
+            let game_state = game.get_game_state();
+            game.include_aabbs = include_aabbs;
+            to_json(&ServerMessage::GameState(&game_state))
+        }
+    }
+}
+
+// Runs `step` on the game, replacing it via `recover_game` if it panics. Returns whether it did.
+fn tick_or_recover(room_name: &str, game: &mut Game, last_good_snapshot: Option<&[u8]>, step: impl FnOnce(&mut Game)) -> bool {
+    let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| step(game))) else {
+        return false;
+    };
+    let message = panic.downcast_ref::<&str>().copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+    error!("Room {} panicked in tick {}: {}", room_name, game.tick, message);
+    *game = recover_game(room_name, game, last_good_snapshot);
+    true
+}
+
+// Builds a replacement for a game whose tick panicked, since rapier may have been left mid-update:
+// the last good snapshot if there is one, else a fresh game. Players rejoin at spawn points with
+// their names; whatever they were holding is dropped.
+fn recover_game(room_name: &str, broken: &Game, last_good_snapshot: Option<&[u8]>) -> Game {
+    let mut game = match last_good_snapshot.map(Game::restore) {
+        Some(Ok(game)) => {
+            warn!("Room {} rolled back to tick {}", room_name, game.tick);
+            game
+        }
+        Some(Err(e)) => {
+            error!("Failed to restore room {} after a panic, starting over: {}", room_name, e);
+            Game::new(None)
+        }
+        None => {
+            warn!("Room {} has no snapshot yet, starting over", room_name);
+            Game::new(None)
+        }
+    };
+    for player_id in broken.player_ids() {
+        game.add_player(player_id);
+        if let Some(name) = broken.player_name(player_id) {
+            // Already sanitized when it was first set.
+            let _ = game.set_player_name(player_id, name);
+        }
+    }
+    game
+}
+
 async fn handle_connection(
-    tls_acceptor: Arc<TokioTlsAcceptor>,
-    peer_map: PeerMap,
-    game: Arc<Mutex<Game>>,
-    input_queue: InputQueue,
+    tls_acceptor: Option<Arc<TokioTlsAcceptor>>,
+    rooms: RoomMap,
+    bans: BanList,
+    state_dir: StateDir,
     raw_stream: TcpStream,
     addr: SocketAddr,
     player_id: PlayerId,
 ) {
     info!("Incoming TCP connection from: {} with player_id: {}", addr, player_id);
 
-    let tls_stream = match tls_acceptor.accept(raw_stream).await {
-        Ok(s) => s,
-        Err(e) => {
-            warn!("Failed to perform TLS handshake with {}: {}", addr, e);
-            return;
-        }
-    };
+    match tls_acceptor {
+        Some(tls_acceptor) => match tls_acceptor.accept(raw_stream).await {
+            Ok(tls_stream) => serve_websocket(tls_stream, rooms, bans, state_dir, addr, player_id).await,
+            Err(e) => warn!("Failed to perform TLS handshake with {}: {}", addr, e),
+        },
+        None => serve_websocket(raw_stream, rooms, bans, state_dir, addr, player_id).await,
+    }
+}
 
-    let ws_stream = match accept_async(tls_stream).await {
+async fn serve_websocket<S>(stream: S, rooms: RoomMap, bans: BanList, state_dir: StateDir, addr: SocketAddr, player_id: PlayerId)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut room_name = DEFAULT_ROOM.to_string();
+    let mut round_seconds = None;
+    let mut practice = false;
+    let ws_stream = match accept_hdr_async(stream, |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+        room_name = room_name_from_path(request.uri().path());
+        round_seconds = round_seconds_from_query(request.uri().query());
+        practice = practice_from_query(request.uri().query());
+        Ok(response)
+    }).await {
         Ok(ws) => ws,
         Err(e) => {
             warn!("Failed to accept websocket connection from {}: {}", addr, e);
             return;
         }
     };
-    info!("WebSocket connection established: {}", addr);
+    let room = if practice {
+        create_practice_room(player_id, round_seconds)
+    } else {
+        get_or_create_room(&rooms, &room_name, round_seconds, state_dir.as_deref().map(PathBuf::as_path)).await
+    };
+    let Some(room) = room else {
+        return;
+    };
+    info!("WebSocket connection established: {} in room {}", addr, room.name);
+    let peer_map = &room.peers;
+    let game = &room.game;
+    let input_queue = &room.input_queue;
 
-    let (mut write, mut read) = ws_stream.split();
+    let (write, mut read) = ws_stream.split();
+    let mut write: PeerSink = Box::pin(write);
 
     let welcome_msg = ServerMessage::Welcome { id: player_id };
-    let welcome_json = serde_json::to_string(&welcome_msg).unwrap();
+    let Some(welcome_json) = to_json(&welcome_msg) else {
+        return;
+    };
     if let Err(e) = write.send(Message::Text(welcome_json)).await {
         warn!("Failed to send welcome message to {}: {}", addr, e);
         return;
     }
 
-    peer_map.lock().await.insert(addr, write);
-    game.lock().await.add_player(player_id);
+    let (width, height, camera) = {
+        let game = game.lock().await;
+        let (width, height) = game.world_dimensions();
+        (width, height, game.camera)
+    };
+    let world_info_msg = ServerMessage::WorldInfo { width, height, camera };
+    let Some(world_info_json) = to_json(&world_info_msg) else {
+        return;
+    };
+    if let Err(e) = write.send(Message::Text(world_info_json)).await {
+        warn!("Failed to send world info to {}: {}", addr, e);
+        return;
+    }
+
+    {
+        let mut game = game.lock().await;
+        // The room may have emptied and closed while this connection was handshaking.
+        if room.closed.load(Ordering::SeqCst) {
+            warn!("Room {} closed before {} could join", room.name, addr);
+            return;
+        }
+        if !game.add_player(player_id) {
+            warn!("Player {} is already in room {}", player_id, room.name);
+        }
+        // The roster is taken under the game lock and the peer map is locked before the game is
+        // released, so joins are ordered the same way in both: a player joining at the same time is
+        // either in this roster or announced to us after it. The sockets are only written once the
+        // game lock is gone, so a slow client can't stall the room's ticks.
+        let roster_msg = ServerMessage::Roster { ids: game.player_ids() };
+        let mut peers = peer_map.lock().await;
+        drop(game);
 
-    while let Some(Ok(msg)) = read.next().await {
-        if let Message::Text(text) = msg {
-            match serde_json::from_str::<PlayerInput>(&text) {
-                Ok(input) => {
-                    input_queue.lock().await.push((player_id, input));
+        let roster_sent = match to_json(&roster_msg) {
+            Some(roster_json) => write.send(Message::Text(roster_json)).await.map_err(|e| warn!("Failed to send roster to {}: {}", addr, e)).is_ok(),
+            None => false,
+        };
+        if !roster_sent {
+            drop(peers);
+            room.game.lock().await.remove_player(player_id);
+            return;
+        }
+        if let Some(joined_json) = to_json(&ServerMessage::PlayerJoined { id: player_id }) {
+            for (peer_addr, writer) in peers.iter_mut() {
+                if let Err(e) = writer.send(Message::Text(joined_json.clone())).await {
+                    warn!("Failed to send message to {}: {}", peer_addr, e);
                 }
-                Err(e) => {
-                    warn!("Failed to deserialize input from {}: {}", addr, e);
+            }
+        }
+        peers.insert(addr, write);
+    }
+    room.keyframe_requests.lock().await.insert(addr);
+    let kicked = Arc::new(Notify::new());
+    room.connections.lock().await.insert(player_id, (addr, kicked.clone()));
+
+    let mut error_limiter = ErrorLimiter::new();
+    let mut admin_attempts = AdminAttempts::new();
+    loop {
+        let next = tokio::select! {
+            next = read.next() => next,
+            _ = kicked.notified() => break,
+        };
+        let Some(Ok(msg)) = next else {
+            break;
+        };
+        match msg {
+            Message::Text(text) => {
+                let message = serde_json::from_str::<ClientMessage>(&text);
+                if let Some(token) = message.as_ref().ok().and_then(ClientMessage::admin_token) {
+                    if !admin_attempts.check(env::var("ADMIN_TOKEN").ok().as_deref(), token) {
+                        warn!("Rejected admin command from {}", addr);
+                        if error_limiter.allow() {
+                            send_error(peer_map, addr, ErrorCode::NotAuthorized, "admin token missing or wrong, or too many wrong tokens".to_string()).await;
+                        }
+                        continue;
+                    }
+                }
+                match message {
+                    Ok(ClientMessage::Input(input)) => {
+                        input_queue.lock().await.push((player_id, input));
+                        room.input_notify.notify_one();
+                    }
+                    Ok(ClientMessage::Subscribe { fields }) => match StateDetail::from_fields(&fields) {
+                        Ok(detail) => {
+                            room.subscriptions.lock().await.insert(addr, detail);
+                            room.keyframe_requests.lock().await.insert(addr);
+                        }
+                        Err(message) => {
+                            warn!("Rejected subscription from {}: {}", addr, message);
+                            if error_limiter.allow() {
+                                send_error(peer_map, addr, ErrorCode::InvalidInput, message).await;
+                            }
+                        }
+                    },
+                    Ok(ClientMessage::Resync) => {
+                        room.keyframe_requests.lock().await.insert(addr);
+                        room.input_notify.notify_one();
+                    }
+                    Ok(ClientMessage::Join { name }) => {
+                        let result = game.lock().await.set_player_name(player_id, &name);
+                        match result {
+                            Ok(()) => info!("Player {} in room {} set their name", player_id, room.name),
+                            Err(e) => {
+                                warn!("Rejected name from {}: {}", addr, e);
+                                if error_limiter.allow() {
+                                    send_error(peer_map, addr, ErrorCode::InvalidInput, e.to_string()).await;
+                                }
+                            }
+                        }
+                    }
+                    Ok(ClientMessage::SetGravity { x, y, .. }) => {
+                        game.lock().await.set_gravity(x, y);
+                        info!("{} set gravity in room {} to ({}, {})", addr, room.name, x, y);
+                        broadcast(peer_map, &ServerMessage::GravityChanged { x, y }).await;
+                        // Wake a settled room so the change takes effect at full rate.
+                        room.input_notify.notify_one();
+                    }
+                    Ok(ClientMessage::Kick { target, .. }) => admin_kick(&room, &bans, addr, target, false, &mut error_limiter).await,
+                    Ok(ClientMessage::Ban { target, .. }) => admin_kick(&room, &bans, addr, target, true, &mut error_limiter).await,
+                    Err(e) => {
+                        warn!("Failed to deserialize message from {}: {}", addr, e);
+                        if error_limiter.allow() {
+                            send_error(peer_map, addr, ErrorCode::InvalidInput, e.to_string()).await;
+                        }
+                    }
                 }
             }
+            Message::Binary(_) => {
+                warn!("Ignoring binary message from {}", addr);
+                if error_limiter.allow() {
+                    send_error(peer_map, addr, ErrorCode::UnsupportedMessage, "binary messages are not supported".to_string()).await;
+                }
+            }
+            _ => {}
         }
     }
 
     info!("{} disconnected", addr);
+    room.connections.lock().await.remove(&player_id);
+    room.subscriptions.lock().await.remove(&addr);
     peer_map.lock().await.remove(&addr);
     game.lock().await.remove_player(player_id);
-}
\ No newline at end of file
+    broadcast(peer_map, &ServerMessage::PlayerLeft { id: player_id }).await;
+    close_room_if_empty(&rooms, &room, state_dir.as_deref().map(PathBuf::as_path)).await;
+}
+
+// A `Kick` or `Ban` from an admin at `addr`, already checked for the right token.
+async fn admin_kick(room: &Room, bans: &BanList, addr: SocketAddr, target: PlayerId, ban: bool, error_limiter: &mut ErrorLimiter) {
+    match kick(room, target).await {
+        Some(target_addr) => {
+            if ban {
+                bans.lock().await.insert(target_addr.ip());
+            }
+            info!("{} {} player {} ({}) from room {}", addr, if ban { "banned" } else { "kicked" }, target, target_addr, room.name);
+        }
+        None => {
+            if error_limiter.allow() {
+                send_error(&room.peers, addr, ErrorCode::InvalidInput, format!("no player {} in this room", target)).await;
+            }
+        }
+    }
+    room.input_notify.notify_one();
+}
+
+// Hangs up on a player, whose own task then cleans up as if they had left. Returns the address
+// they were connected from, or `None` if they aren't in this room.
+async fn kick(room: &Room, target: PlayerId) -> Option<SocketAddr> {
+    let (addr, kicked) = room.connections.lock().await.get(&target).cloned()?;
+    let writer = room.peers.lock().await.remove(&addr);
+    if let Some(mut writer) = writer {
+        if let Err(e) = writer.close().await {
+            warn!("Failed to close connection to {}: {}", addr, e);
+        }
+    }
+    kicked.notify_one();
+    Some(addr)
+}
+
+// A serialization failure drops the one message instead of panicking the room's loop.
+fn to_json(msg: &ServerMessage<'_>) -> Option<String> {
+    serde_json::to_string(msg)
+        .map_err(|e| error!("Failed to serialize server message: {}", e))
+        .ok()
+}
+
+async fn send_error(peer_map: &PeerMap, addr: SocketAddr, code: ErrorCode, message: String) {
+    let Some(json) = to_json(&ServerMessage::Error { code, message }) else {
+        return;
+    };
+    if let Some(writer) = peer_map.lock().await.get_mut(&addr) {
+        if let Err(e) = writer.send(Message::Text(json)).await {
+            warn!("Failed to send error to {}: {}", addr, e);
+        }
+    }
+}
+
+async fn broadcast(peer_map: &PeerMap, msg: &ServerMessage<'_>) {
+    let Some(json) = to_json(msg) else {
+        return;
+    };
+    let mut peers = peer_map.lock().await;
+    for (addr, writer) in peers.iter_mut() {
+        if let Err(e) = writer.send(Message::Text(json.clone())).await {
+            warn!("Failed to send message to {}: {}", addr, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(mouse_dx: f32, mouse_dy: f32) -> PlayerInput {
+        serde_json::from_value(serde_json::json!({ "mouse_dx": mouse_dx, "mouse_dy": mouse_dy, "is_mouse_down": false })).unwrap()
+    }
+
+    async fn player_position(room: &Room, player_id: PlayerId) -> (f32, f32) {
+        let players = room.game.lock().await.get_game_state().players;
+        let player = players.iter().find(|player| player.id == player_id).unwrap();
+        (player.x, player.y)
+    }
+
+    fn temp_state_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("cursorarena-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A still, gravity-free map, so the restored room's own loop ticking doesn't move anything.
+    fn still_game() -> Game {
+        let map = serde_json::from_str(r#"{ "dimensions": [20.0, 10.0], "gravity": [0.0, 0.0], "entities": [
+            { "shape": "rect", "x1": 0.1, "y1": 0.1, "x2": 0.2, "y2": 0.3 },
+            { "shape": "circle", "x": 0.7, "y": 0.4, "radius_units": 0.5 }
+        ] }"#).unwrap();
+        Game::try_new(Some(map)).unwrap()
+    }
+
+    fn object_layout(game: &Game) -> Vec<(u32, f32, f32)> {
+        game.get_game_state().objects.iter().map(|object| (object.id, object.x, object.y)).collect()
+    }
+
+    fn state_for(game: &mut Game, fields: &[&str]) -> serde_json::Value {
+        let fields: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+        let detail = StateDetail::from_fields(&fields).unwrap();
+        serde_json::from_str(&full_state_json(game, detail).unwrap()).unwrap()
+    }
+
+    // Tests that run a room's game loop start with tokio's clock paused. It moves on only when every
+    // task is waiting, so a sleep in them is an exact stretch of game time however slow the machine.
+
+    // A peer whose messages land in the returned channel instead of a socket.
+    async fn add_test_peer(room: &Room) -> (SocketAddr, tokio::sync::mpsc::UnboundedReceiver<Message>) {
+        let (sender, messages) = tokio::sync::mpsc::unbounded_channel();
+        let sink = futures_util::sink::unfold(sender, |sender, message: Message| async move {
+            let _ = sender.send(message);
+            Ok::<_, WsError>(sender)
+        });
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        room.peers.lock().await.insert(addr, Box::pin(sink));
+        (addr, messages)
+    }
+
+    // The next state message a test peer got, skipping state hashes.
+    async fn next_state(messages: &mut tokio::sync::mpsc::UnboundedReceiver<Message>) -> serde_json::Value {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(2), messages.recv()).await.unwrap().unwrap();
+            let state: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+            if state["type"] != "StateHash" {
+                return state;
+            }
+        }
+    }
+
+    // Connects a websocket client to `url` through an in-memory stream and returns it once the
+    // server has sent the roster.
+    async fn join(rooms: &RoomMap, url: &str, player_id: PlayerId) -> (tokio_tungstenite::WebSocketStream<tokio::io::DuplexStream>, serde_json::Value) {
+        let (client, server) = tokio::io::duplex(1 << 16);
+        let addr: SocketAddr = format!("127.0.0.1:{}", 9100 + player_id).parse().unwrap();
+        tokio::spawn(serve_websocket(server, rooms.clone(), BanList::default(), None, addr, player_id));
+        let (mut socket, _) = tokio_tungstenite::client_async(url, client).await.unwrap();
+        loop {
+            let message = socket.next().await.unwrap().unwrap();
+            let message: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+            if message["type"] == "Roster" {
+                return (socket, message["ids"].clone());
+            }
+        }
+    }
+
+    #[test]
+    fn position_subscribers_get_only_object_positions() {
+        let mut game = still_game();
+        let state = state_for(&mut game, &["position"]);
+        assert_eq!(state["type"], "ObjectPositions");
+        for object in state["objects"].as_array().unwrap() {
+            let mut keys: Vec<&str> = object.as_object().unwrap().keys().map(String::as_str).collect();
+            keys.sort_unstable();
+            assert_eq!(keys, ["id", "rotation", "x", "y"]);
+        }
+
+        let standard = state_for(&mut game, &[]);
+        assert!(standard["objects"][0].get("shape").is_some());
+        assert!(standard["objects"][0].get("aabb").is_none());
+        let debug = state_for(&mut game, &["position", "debug"]);
+        assert!(debug["objects"][0]["aabb"].is_array());
+        assert!(StateDetail::from_fields(&["shape".to_string()]).is_err());
+    }
+
+    #[test]
+    fn panicking_tick_rolls_back_to_the_last_good_snapshot() {
+        let mut game = still_game();
+        game.add_player(1);
+        for _ in 0..3 {
+            game.tick();
+        }
+        let snapshot = game.snapshot().unwrap();
+        let (good_tick, good_layout) = (game.tick, object_layout(&game));
+        for _ in 0..5 {
+            game.tick();
+        }
+
+        assert!(tick_or_recover("broken", &mut game, Some(&snapshot), |_| panic!("bad step")));
+        assert_eq!(game.tick, good_tick);
+        assert_eq!(object_layout(&game), good_layout);
+        assert_eq!(game.player_ids(), vec![1]);
+
+        assert!(!tick_or_recover("broken", &mut game, Some(&snapshot), |game| {
+            game.tick();
+        }));
+        assert_eq!(game.tick, good_tick + 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn saved_rooms_reload_with_the_same_state() {
+        let dir = temp_state_dir("save");
+        let mut game = still_game();
+        for _ in 0..10 {
+            game.tick();
+        }
+        save_room("saved", game.snapshot(), &dir).await;
+
+        let rooms = RoomMap::default();
+        load_rooms(&rooms, &dir).await;
+        let room = rooms.lock().await.get("saved").cloned().unwrap();
+        let restored = room.game.lock().await;
+        assert_eq!(restored.world_dimensions(), (20.0, 10.0));
+        assert!(restored.tick >= 10);
+        assert_eq!(object_layout(&restored), object_layout(&game));
+        room.closed.store(true, Ordering::SeqCst);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restored_rooms_keep_simulating_from_where_they_were_saved() {
+        let dir = temp_state_dir("resume");
+        let falling = serde_json::from_str(r#"{ "entities": [{ "shape": "circle", "x": 0.5, "y": 0.8, "radius": 0.05 }] }"#).unwrap();
+        let mut game = Game::try_new(Some(falling)).unwrap();
+        for _ in 0..10 {
+            game.tick();
+        }
+        save_room("falling", game.snapshot(), &dir).await;
+
+        let rooms = RoomMap::default();
+        load_rooms(&rooms, &dir).await;
+        let room = rooms.lock().await.get("falling").cloned().unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        // The room's own loop carried on from tick 10, exactly as the saved game would have.
+        let restored = room.game.lock().await;
+        assert!(restored.tick > 20);
+        while game.tick < restored.tick {
+            game.tick();
+        }
+        for (resumed, expected) in object_layout(&restored).into_iter().zip(object_layout(&game)) {
+            assert_eq!(resumed.0, expected.0);
+            assert!((resumed.1 - expected.1).abs() < 1e-3 && (resumed.2 - expected.2).abs() < 1e-3, "{:?} vs {:?}", resumed, expected);
+        }
+        drop(restored);
+
+        // Nobody joined, so once the grace period is over the room closes and is saved again.
+        tokio::time::sleep(RESTORED_ROOM_GRACE + Duration::from_secs(1)).await;
+        assert!(room.closed.load(Ordering::SeqCst));
+        assert!(rooms.lock().await.get("falling").is_none());
+        let saved = Game::restore(&std::fs::read(dir.join("falling.json")).unwrap()).unwrap();
+        assert!(saved.tick > 20);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn emptied_room_is_saved_and_reopened() {
+        let dir = temp_state_dir("reopen");
+        let rooms = RoomMap::default();
+        let room = get_or_create_room(&rooms, "keep", None, Some(dir.as_path())).await.unwrap();
+        room.game.lock().await.add_player(1);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let left_at_tick = {
+            let mut game = room.game.lock().await;
+            game.remove_player(1);
+            game.tick
+        };
+        assert!(left_at_tick > 0);
+        close_room_if_empty(&rooms, &room, Some(dir.as_path())).await;
+        assert!(rooms.lock().await.get("keep").is_none());
+        assert!(dir.join("keep.json").exists());
+
+        let reopened = get_or_create_room(&rooms, "keep", None, Some(dir.as_path())).await.unwrap();
+        assert!(!Arc::ptr_eq(&room, &reopened));
+        assert!(reopened.game.lock().await.tick >= left_at_tick);
+    }
+
+    #[tokio::test]
+    async fn corrupt_saved_room_starts_fresh() {
+        let dir = temp_state_dir("corrupt");
+        let path = dir.join("broken.json");
+        std::fs::write(&path, b"not a snapshot").unwrap();
+
+        let rooms = RoomMap::default();
+        load_rooms(&rooms, &dir).await;
+        assert!(rooms.lock().await.get("broken").is_none());
+        assert!(!path.exists());
+
+        let room = get_or_create_room(&rooms, "broken", None, Some(dir.as_path())).await.unwrap();
+        assert_eq!(room.game.lock().await.world_dimensions(), (16.0, 9.0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flooding_input_does_not_speed_up_ticks() {
+        let empty_map = serde_json::from_str("{}").unwrap();
+        let room = spawn_room("flood", Game::try_new(Some(empty_map)).unwrap(), None);
+        room.game.lock().await.add_player(1);
+        let started = tokio::time::Instant::now();
+        while started.elapsed() < Duration::from_millis(500) {
+            room.input_queue.lock().await.push((1, input(0.0, 0.0)));
+            room.input_notify.notify_one();
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let max_ticks = (started.elapsed().as_millis() / TICK_PERIOD.as_millis()) as u64 + 2;
+        assert!(room.game.lock().await.tick <= max_ticks);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rooms_keep_inputs_and_state_apart() {
+        let rooms = RoomMap::default();
+        let first = get_or_create_room(&rooms, "first", None, None).await.unwrap();
+        let second = get_or_create_room(&rooms, "second", None, None).await.unwrap();
+        assert!(!Arc::ptr_eq(&first.game, &second.game));
+        first.game.lock().await.add_player(1);
+        second.game.lock().await.add_player(1);
+
+        first.input_queue.lock().await.push((1, input(1.0, 0.0)));
+        first.input_notify.notify_one();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        assert!(player_position(&first, 1).await.0 > 0.5);
+        assert_eq!(player_position(&second, 1).await, (0.0, 0.0));
+        assert!(second.input_queue.lock().await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timed_rounds_end_on_the_tick_boundary() {
+        let room = spawn_room("timed", Game::new(None), Some(1));
+        room.game.lock().await.add_score(1, 5);
+        loop {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            let game = room.game.lock().await;
+            if game.tick < TICKS_PER_SECOND {
+                assert_eq!(game.scores().get(&1), Some(&5));
+            } else {
+                assert!(game.scores().is_empty());
+                break;
+            }
+        }
+        room.closed.store(true, Ordering::SeqCst);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resync_requests_get_the_full_object_list() {
+        let room = spawn_room("resync", still_game(), None);
+        let (addr, mut messages) = add_test_peer(&room).await;
+        room.game.lock().await.add_player(1);
+        while next_state(&mut messages).await["type"] != "PlayersUpdate" {}
+
+        // Asked for just before a players-only tick, with nothing from earlier ticks still queued.
+        loop {
+            let game = room.game.lock().await;
+            let next_broadcast = game.tick / room.broadcast_every + 1;
+            if next_broadcast % FULL_SNAPSHOT_INTERVAL_BROADCASTS != 0 {
+                while messages.try_recv().is_ok() {}
+                room.keyframe_requests.lock().await.insert(addr);
+                break;
+            }
+            drop(game);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let state = next_state(&mut messages).await;
+        assert_eq!(state["type"], "GameState");
+        assert_eq!(state["objects"].as_array().unwrap().len(), 2);
+        room.closed.store(true, Ordering::SeqCst);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn broadcasting_every_other_tick_halves_the_sends() {
+        let room = Arc::new(Room {
+            name: "every-other".to_string(),
+            peers: PeerMap::new(Mutex::new(HashMap::new())),
+            connections: Mutex::new(HashMap::new()),
+            game: Arc::new(Mutex::new(Game::new(None))),
+            input_queue: InputQueue::new(Mutex::new(Vec::new())),
+            input_notify: Notify::new(),
+            round_length_ticks: None,
+            keyframe_requests: Mutex::new(HashSet::new()),
+            subscriptions: Mutex::new(HashMap::new()),
+            closed: AtomicBool::new(false),
+            broadcast_every: 2,
+        });
+        let (_, mut messages) = add_test_peer(&room).await;
+        tokio::spawn(game_loop(room.clone()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Counted under the game lock, so no tick is half sent at either end.
+        let start_tick = {
+            let game = room.game.lock().await;
+            while messages.try_recv().is_ok() {}
+            game.tick
+        };
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let game = room.game.lock().await;
+        let mut sends = 0;
+        while let Ok(message) = messages.try_recv() {
+            let state: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+            if state["type"] != "StateHash" {
+                sends += 1;
+            }
+        }
+        assert!(game.tick - start_tick >= 4);
+        assert_eq!(sends, game.tick / 2 - start_tick / 2);
+        room.closed.store(true, Ordering::SeqCst);
+    }
+
+    #[tokio::test]
+    async fn practice_rooms_are_hidden_from_other_connections() {
+        let rooms = RoomMap::default();
+        let (_practice, roster) = join(&rooms, "ws://localhost/lobby?practice=1", 1).await;
+        assert_eq!(roster, serde_json::json!([1]));
+        assert!(rooms.lock().await.is_empty());
+
+        let (_lobby, roster) = join(&rooms, "ws://localhost/lobby", 2).await;
+        assert_eq!(roster, serde_json::json!([2]));
+        let lobby = rooms.lock().await.get("lobby").cloned().unwrap();
+        assert_eq!(lobby.game.lock().await.player_ids(), vec![2]);
+        assert_eq!(rooms.lock().await.len(), 1);
+    }
+    #[tokio::test]
+    async fn players_hear_about_everyone_who_joins_after_them() {
+        let rooms = RoomMap::default();
+        let (mut first, roster) = join(&rooms, "ws://localhost/lobby", 1).await;
+        assert_eq!(roster, serde_json::json!([1]));
+        let (_second, roster) = join(&rooms, "ws://localhost/lobby", 2).await;
+        assert_eq!(roster, serde_json::json!([1, 2]));
+        loop {
+            let message = first.next().await.unwrap().unwrap();
+            let message: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+            if message["type"] == "PlayerJoined" {
+                assert_eq!(message["id"], 2);
+                break;
+            }
+        }
+    }
+    #[test]
+    fn client_messages_are_told_apart_by_their_type() {
+        let input = r#"{ "type": "input", "mouse_dx": 1.0, "mouse_dy": 0.0, "is_mouse_down": false }"#;
+        assert!(matches!(serde_json::from_str::<ClientMessage>(input), Ok(ClientMessage::Input(_))));
+        assert!(matches!(serde_json::from_str::<ClientMessage>(r#"{ "type": "resync" }"#), Ok(ClientMessage::Resync)));
+
+        // A broken message names what is wrong with it.
+        let error = serde_json::from_str::<ClientMessage>(r#"{ "type": "SetGravity", "token": "t", "x": 1.0 }"#).err().unwrap();
+        assert!(error.to_string().contains("missing field `y`"), "{}", error);
+        let error = serde_json::from_str::<ClientMessage>(r#"{ "mouse_dx": 1.0, "mouse_dy": 0.0, "is_mouse_down": false }"#).err().unwrap();
+        assert!(error.to_string().contains("missing field `type`"), "{}", error);
+    }
+    #[test]
+    fn repeated_wrong_admin_tokens_lock_the_connection_out() {
+        assert!(!tokens_match("secret", "secreT") && !tokens_match("secret", "secret!") && !tokens_match("secret", ""));
+        assert!(!AdminAttempts::new().check(None, "secret"));
+        assert!(!AdminAttempts::new().check(Some(""), ""));
+
+        let mut attempts = AdminAttempts::new();
+        assert!(attempts.check(Some("secret"), "secret"));
+        for _ in 0..MAX_FAILED_ADMIN_ATTEMPTS {
+            assert!(!attempts.check(Some("secret"), "guess"));
+        }
+        assert!(!attempts.check(Some("secret"), "secret"));
+    }
+}
@@ -1,5 +1,7 @@
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::Response;
 use game_logic::{Game as GameLogic, MapData, PlayerInput};
 
 #[wasm_bindgen]
@@ -8,15 +10,48 @@ pub struct Game(GameLogic);
 #[wasm_bindgen]
 impl Game {
     #[wasm_bindgen(constructor)]
-    pub fn new(map_data_js: &JsValue) -> Self {
+    pub fn new(map_data_js: &JsValue) -> Result<Game, JsValue> {
+        Self::new_with_options(map_data_js, true)
+    }
+
+    // `add_default_player: false` gives an empty game, e.g. for a replay viewer or a page that
+    // adds its own players.
+    pub fn new_with_options(map_data_js: &JsValue, add_default_player: bool) -> Result<Game, JsValue> {
         let map_data: Option<MapData> = if map_data_js.is_null() || map_data_js.is_undefined() {
             None
         } else {
-            serde_wasm_bindgen::from_value(map_data_js.clone()).ok()
+            let map_data = serde_wasm_bindgen::from_value(map_data_js.clone())
+                .map_err(|e| JsValue::from_str(&format!("invalid map data: {}", e)))?;
+            Some(map_data)
         };
-        let mut game = GameLogic::new(map_data);
-        game.add_player(0); // Add a default player for local game
-        Self(game)
+        Self::with_map(map_data, add_default_player)
+    }
+
+    // Fetches a map's JSON and builds a game from it; the promise rejects with a message if the
+    // fetch fails or the map is invalid. From JS:
+    //
+    //     const game = await Game.load_map_from_url(`/maps/${mapId}.json`);
+    pub async fn load_map_from_url(url: String) -> Result<Game, JsValue> {
+        let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window to fetch the map from"))?;
+        let response: Response = JsFuture::from(window.fetch_with_str(&url)).await
+            .map_err(|e| JsValue::from_str(&format!("failed to fetch map {}: {:?}", url, e)))?
+            .dyn_into()?;
+        if !response.ok() {
+            return Err(JsValue::from_str(&format!("failed to fetch map {}: HTTP {}", url, response.status())));
+        }
+        let text = JsFuture::from(response.text()?).await?
+            .as_string()
+            .ok_or_else(|| JsValue::from_str("map response is not text"))?;
+        let map_data: MapData = serde_json::from_str(&text).map_err(|e| JsValue::from_str(&format!("invalid map data: {}", e)))?;
+        Self::with_map(Some(map_data), true)
+    }
+
+    fn with_map(map_data: Option<MapData>, add_default_player: bool) -> Result<Game, JsValue> {
+        let mut game = GameLogic::try_new(map_data).map_err(|e| JsValue::from_str(&format!("invalid map: {}", e)))?;
+        if add_default_player {
+            game.add_player(0); // Add a default player for local game
+        }
+        Ok(Self(game))
     }
 
     pub fn tick(&mut self, mouse_dx: f32, mouse_dy: f32, is_mouse_down: bool) {
@@ -24,6 +59,34 @@ impl Game {
             mouse_dx,
             mouse_dy,
             is_mouse_down,
+            scale_factor: None,
+            dash: false,
+            dash_dir: (0.0, 0.0),
+            grab_pressed: false,
+            grab_released: false,
+            spawn_request: None,
+            mouse_path: None,
+        };
+        self.0.apply_input(0, input);
+        self.0.tick();
+    }
+
+    // Like `tick`, with the frame's mouse movement as `[dx0, dy0, dx1, dy1, ...]` samples taken at
+    // the display's refresh rate, so fast flicks follow their path.
+    pub fn tick_with_path(&mut self, path: Vec<f32>, is_mouse_down: bool) {
+        let samples: Vec<(f32, f32)> = path.chunks_exact(2).map(|sample| (sample[0], sample[1])).collect();
+        let (mouse_dx, mouse_dy) = samples.iter().fold((0.0, 0.0), |(x, y), (dx, dy)| (x + dx, y + dy));
+        let input = PlayerInput {
+            mouse_dx,
+            mouse_dy,
+            is_mouse_down,
+            scale_factor: None,
+            dash: false,
+            dash_dir: (0.0, 0.0),
+            grab_pressed: false,
+            grab_released: false,
+            spawn_request: None,
+            mouse_path: Some(samples),
         };
         self.0.apply_input(0, input);
         self.0.tick();
@@ -31,7 +94,34 @@ impl Game {
 
     pub fn get_game_state(&self) -> String {
         let game_state = self.0.get_game_state();
-        serde_json::to_string(&game_state).unwrap()
+        serde_json::to_string(&game_state).unwrap_or_else(|e| {
+            web_sys::console::error_1(&JsValue::from_str(&format!("failed to serialize game state: {}", e)));
+            r#"{"players":[],"objects":[],"boundaries":[],"gravity":[0,0]}"#.to_string()
+        })
+    }
+
+    // `alpha` is how far the renderer is between the last physics tick and the next, from 0 to 1.
+    pub fn get_interpolated_state(&self, alpha: f32) -> String {
+        let game_state = self.0.get_interpolated_state(alpha);
+        serde_json::to_string(&game_state).unwrap_or_else(|e| {
+            web_sys::console::error_1(&JsValue::from_str(&format!("failed to serialize game state: {}", e)));
+            r#"{"players":[],"objects":[],"boundaries":[],"gravity":[0,0]}"#.to_string()
+        })
+    }
+
+    // The current scene as map JSON that `Game::new` can load again.
+    pub fn export_map(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.0.export_map()).map_err(|e| JsValue::from_str(&format!("failed to serialize map: {}", e)))
+    }
+
+    // See `game_logic::Game::get_state_buffer` for the layout.
+    pub fn get_state_buffer(&self) -> Vec<u8> {
+        self.0.get_state_buffer()
+    }
+
+    #[wasm_bindgen]
+    pub fn set_gravity(&mut self, x: f32, y: f32) {
+        self.0.set_gravity(x, y);
     }
 
     #[wasm_bindgen]
@@ -43,4 +133,15 @@ impl Game {
     pub fn restart(&mut self) {
         self.0.restart();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn games_without_the_default_player_start_empty() {
+        assert!(Game::with_map(None, false).unwrap().0.is_empty());
+        assert_eq!(Game::with_map(None, true).unwrap().0.player_ids(), vec![0]);
+    }
+}